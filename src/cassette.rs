@@ -0,0 +1,337 @@
+//! Record and replay the HTTP traffic of the [`Client`](crate::Client).
+//!
+//! A cassette is a directory with every GraphQL exchange and CDN fetch saved to disk:
+//! - `graphql/<operation>_<key>.json` contains the request body and the response.
+//! - `cdn/<key>.bin` contains the raw (still encrypted) image, with `cdn/<key>.json` as the metadata.
+//!
+//! The `<key>` is derived from the request, so the same request will always map to the same file.
+//! The CDN key ignores the query string, since the signed URLs in the replayed responses are redacted.
+//!
+//! Tokens and other credentials are redacted before anything is written, including the query string
+//! of every URL in the GraphQL bodies.
+//!
+//! Only the GraphQL and CDN traffic is recorded, the token refresh is skipped entirely when replaying.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::hash_bytes;
+
+/// Keys in a JSON body whose values will be redacted when recording
+const SENSITIVE_KEYS: &[&str] = &[
+    "idToken",
+    "refreshToken",
+    "accessToken",
+    "id_token",
+    "refresh_token",
+    "access_token",
+    "password",
+    "email",
+    "userId",
+    "dateOfBirth",
+    "pronouns",
+];
+const REDACTED: &str = "<redacted>";
+
+/// The mode of the cassette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Capture every request made to the directory
+    Record,
+    /// Serve every request from the directory, no network request will be made
+    Replay,
+}
+
+/// Error type for the cassette
+pub enum CassetteError {
+    Io(std::io::Error),
+    Serde(serde_json::Error),
+    /// The request is not recorded in the cassette
+    Missing(String),
+}
+
+impl From<std::io::Error> for CassetteError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for CassetteError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Serde(e)
+    }
+}
+
+impl std::fmt::Display for CassetteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Serde(e) => write!(f, "Serde Error: {}", e),
+            Self::Missing(what) => write!(f, "Request not found in cassette: {}", what),
+        }
+    }
+}
+
+impl std::fmt::Debug for CassetteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {:?}", e),
+            Self::Serde(e) => write!(f, "Serde Error: {:?}", e),
+            Self::Missing(what) => write!(f, "Request not found in cassette: {}", what),
+        }
+    }
+}
+
+/// A recorded GraphQL exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphQLEntry {
+    /// The GraphQL API URL
+    pub url: String,
+    /// The request headers, with the credentials redacted
+    pub headers: Vec<(String, String)>,
+    /// The request body (the query and the variables)
+    pub request: serde_json::Value,
+    /// The response status code
+    pub status: u16,
+    /// The response content type
+    pub content_type: Option<String>,
+    /// The full response body
+    ///
+    /// This is stored as JSON if possible to make it easier to read and diff.
+    pub response: serde_json::Value,
+}
+
+impl GraphQLEntry {
+    /// Get the response body as text
+    pub fn response_text(&self) -> String {
+        match &self.response {
+            serde_json::Value::String(text) => text.clone(),
+            value => value.to_string(),
+        }
+    }
+}
+
+/// Metadata of a recorded CDN fetch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CdnEntry {
+    /// The URL with the query string redacted
+    pub url: String,
+    /// The size of the data in bytes
+    pub size: usize,
+    /// SHA-256 hash of the data
+    pub hash: String,
+}
+
+/// A directory of recorded HTTP traffic
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    mode: CassetteMode,
+    directory: PathBuf,
+}
+
+impl Cassette {
+    /// Create a cassette that records into the directory, the directory will be created if needed
+    pub fn record(directory: impl Into<PathBuf>) -> Result<Self, CassetteError> {
+        let directory: PathBuf = directory.into();
+        std::fs::create_dir_all(directory.join("graphql"))?;
+        std::fs::create_dir_all(directory.join("cdn"))?;
+
+        Ok(Self {
+            mode: CassetteMode::Record,
+            directory,
+        })
+    }
+
+    /// Create a cassette that replays from the directory
+    pub fn replay(directory: impl Into<PathBuf>) -> Result<Self, CassetteError> {
+        let directory: PathBuf = directory.into();
+        if !directory.is_dir() {
+            return Err(CassetteError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Cassette directory {} does not exist", directory.display()),
+            )));
+        }
+
+        Ok(Self {
+            mode: CassetteMode::Replay,
+            directory,
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == CassetteMode::Replay
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn graphql_path(&self, request: &serde_json::Value) -> PathBuf {
+        let operation = request
+            .get("query")
+            .and_then(|q| q.as_str())
+            .and_then(operation_name)
+            .unwrap_or("anonymous");
+        let key = request_key(request.to_string().as_bytes());
+
+        self.directory
+            .join("graphql")
+            .join(format!("{}_{}.json", operation, key))
+    }
+
+    fn cdn_path(&self, url: &str, extension: &str) -> PathBuf {
+        let key = request_key(redact_url(url).as_bytes());
+
+        self.directory
+            .join("cdn")
+            .join(format!("{}.{}", key, extension))
+    }
+
+    /// Save a GraphQL exchange to the cassette
+    pub(crate) fn record_graphql(
+        &self,
+        url: &reqwest::Url,
+        headers: &reqwest::header::HeaderMap,
+        request: &serde_json::Value,
+        status: reqwest::StatusCode,
+        content_type: Option<&str>,
+        response: &str,
+    ) -> Result<(), CassetteError> {
+        let response = match serde_json::from_str::<serde_json::Value>(response) {
+            Ok(mut value) => {
+                redact_json(&mut value);
+                value
+            }
+            Err(_) => serde_json::Value::String(response.to_string()),
+        };
+        let mut request_body = request.clone();
+        redact_json(&mut request_body);
+
+        let entry = GraphQLEntry {
+            url: url.to_string(),
+            headers: redact_headers(headers),
+            request: request_body,
+            status: status.as_u16(),
+            content_type: content_type.map(|c| c.to_string()),
+            response,
+        };
+
+        let results = serde_json::to_string_pretty(&entry)?;
+        std::fs::write(self.graphql_path(request), results)?;
+
+        Ok(())
+    }
+
+    /// Find the recorded GraphQL exchange for the request
+    pub(crate) fn replay_graphql(
+        &self,
+        request: &serde_json::Value,
+    ) -> Result<GraphQLEntry, CassetteError> {
+        let path = self.graphql_path(request);
+        if !path.exists() {
+            return Err(CassetteError::Missing(path.display().to_string()));
+        }
+
+        let file = std::fs::File::open(path)?;
+        let entry: GraphQLEntry = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+        Ok(entry)
+    }
+
+    /// Save a CDN fetch to the cassette
+    pub(crate) fn record_cdn(&self, url: &str, data: &[u8]) -> Result<(), CassetteError> {
+        let entry = CdnEntry {
+            url: redact_url(url),
+            size: data.len(),
+            hash: hash_bytes(data),
+        };
+
+        std::fs::write(self.cdn_path(url, "bin"), data)?;
+        std::fs::write(
+            self.cdn_path(url, "json"),
+            serde_json::to_string_pretty(&entry)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Read the recorded CDN fetch for the URL
+    pub(crate) fn replay_cdn(&self, url: &str) -> Result<Vec<u8>, CassetteError> {
+        let path = self.cdn_path(url, "bin");
+        if !path.exists() {
+            return Err(CassetteError::Missing(redact_url(url)));
+        }
+
+        Ok(std::fs::read(path)?)
+    }
+}
+
+/// Get the operation name from a GraphQL query, e.g. `query getVolumes(...)` is `getVolumes`
+fn operation_name(query: &str) -> Option<&str> {
+    let query = query.trim_start();
+    let rest = query
+        .strip_prefix("query")
+        .or_else(|| query.strip_prefix("mutation"))?;
+    let name = rest
+        .trim_start()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()?;
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn request_key(data: &[u8]) -> String {
+    hash_bytes(data)[..16].to_string()
+}
+
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::String(text)
+            if text.starts_with("https://") || text.starts_with("http://") =>
+        {
+            *text = redact_url(text);
+        }
+        _ => {}
+    }
+}
+
+fn redact_headers(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match name.as_str() {
+                "authorization" => format!("Bearer {}", REDACTED),
+                "x-hash" | "cookie" => REDACTED.to_string(),
+                _ => value.to_str().unwrap_or_default().to_string(),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Remove the query string from the URL since it usually contains the signature
+fn redact_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((base, _)) => format!("{}?{}", base, REDACTED),
+        None => url.to_string(),
+    }
+}
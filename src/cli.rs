@@ -3,11 +3,21 @@ use clap::{
         styling::{AnsiColor, Effects},
         Styles,
     },
-    Parser, Subcommand,
+    Args, Parser, Subcommand, ValueEnum,
+};
+use emancipated::{
+    device::{SplitOrder, DEVICE_PROFILES},
+    spread::SpreadMode,
+    transcode::OutputFormat,
 };
 
 pub(crate) type ExitCode = i32;
 
+/// Default concurrent page downloads when using `--parallel`
+pub(crate) const DEFAULT_JOBS: usize = 4;
+/// Default directory for the downloads, relative to the current directory
+pub(crate) const DEFAULT_OUTPUT: &str = "DOWNLOADS";
+
 #[derive(Parser)]
 #[command(name = "emancipated")]
 #[command(bin_name = "emancipated")]
@@ -25,6 +35,16 @@ pub(crate) struct EmancipatedCli {
     /// `http(s)://<username>:<password>@<ip>:<port>` or `socks5://<username>:<password>@<ip>:<port>`.
     #[arg(long)]
     pub(crate) proxy: Option<String>,
+    /// Record all the API requests and image downloads into the directory
+    ///
+    /// Tokens and other credentials are redacted, the directory can be attached to a bug report.
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub(crate) record: Option<std::path::PathBuf>,
+    /// Replay the API requests and image downloads from a directory made with `--record`
+    ///
+    /// No network requests will be made, the same account used to record is required to decrypt the images.
+    #[arg(long, value_name = "DIR")]
+    pub(crate) replay: Option<std::path::PathBuf>,
 
     /// Email/Account to use
     #[arg(short = 'a', long = "account", default_value = None)]
@@ -34,29 +54,241 @@ pub(crate) struct EmancipatedCli {
     pub(crate) command: EmancipatedCommands,
 }
 
+/// Where to get the password when authenticating
+#[derive(Args, Clone)]
+pub(crate) struct PasswordArgs {
+    /// Password to use (not recommended, it will be saved in your shell history)
+    ///
+    /// When omitted, the password is read from `--password-file`, `--password-stdin`,
+    /// the `EMANCIPATED_PASSWORD` environment variable, or prompted interactively.
+    pub(crate) password: Option<String>,
+    /// Read the password from the first line of the file
+    #[arg(long = "password-file", value_name = "PATH", conflicts_with_all = ["password", "password_stdin"])]
+    pub(crate) password_file: Option<std::path::PathBuf>,
+    /// Read the password from the first line of the standard input
+    #[arg(long = "password-stdin", conflicts_with = "password")]
+    pub(crate) password_stdin: bool,
+}
+
+#[derive(Subcommand, Clone)]
+pub(crate) enum AccountsCommands {
+    /// List all the accounts (default)
+    List,
+    /// Remove an account alongside its key pair
+    #[command(alias = "logout")]
+    Remove {
+        /// Email of the account to remove
+        email: String,
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// Set the default account so you will not be prompted to select one
+    ///
+    /// Shows the current default account when no email is given.
+    Default {
+        /// Email of the account to use by default
+        email: Option<String>,
+        /// Clear the default account
+        #[arg(long = "clear", conflicts_with = "email")]
+        clear: bool,
+    },
+    /// Protect all the accounts and private keys with a passphrase
+    ///
+    /// Can also be used to change the passphrase. The passphrase can be provided with
+    /// the `EMANCIPATED_PASSPHRASE` environment variable for non-interactive usage.
+    Encrypt,
+    /// Remove the passphrase protection from all the accounts and private keys
+    Decrypt,
+    /// Login again to refresh the credentials while keeping the key pair
+    Relogin {
+        /// Email of the account, defaults to `--account` or the selected account
+        email: Option<String>,
+        #[command(flatten)]
+        password: PasswordArgs,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub(crate) enum ConfigCommands {
+    /// Show the current and effective settings
+    Show,
+    /// Change a setting
+    ///
+    /// Available settings: `output`, `jobs`, `proxy`, `format`, `retries`, and `retry_delay`.
+    Set {
+        /// The setting name
+        key: String,
+        /// The new value
+        value: String,
+    },
+    /// Remove a setting and use the default value
+    Unset {
+        /// The setting name
+        key: String,
+    },
+}
+
 #[derive(Subcommand, Clone)]
 pub(crate) enum EmancipatedCommands {
     /// Authenticate with your account.
     Auth {
         /// Email to use
         email: String,
-        /// Password to use
-        password: String,
+        #[command(flatten)]
+        password: PasswordArgs,
     },
     /// Get an account information
     Account,
-    /// See all the accounts you have authenticated with
-    Accounts,
+    /// See and manage all the accounts you have authenticated with
+    Accounts {
+        #[command(subcommand)]
+        action: Option<AccountsCommands>,
+    },
     /// Download specific volumes of a title
     Download {
         /// Slug of the title
         slug: String,
         /// Specify the volume(s) to download
+        ///
+        /// Accepts a comma separated list of volumes or ranges, e.g. `1-5,8,10-`.
+        ///
+        /// You can also use `all` to download every purchased volume or `latest` for the latest volume.
         #[arg(short = 'n', long = "volume")]
-        volume: u32,
+        volume: VolumeSelector,
         /// Enable parallel download
         #[arg(short = 'p', long = "parallel")]
         parallel: bool,
+        /// Maximum concurrent page downloads for the whole volume
+        ///
+        /// Setting this implies `--parallel`, defaults to the `jobs` setting or 4 workers when `--parallel` is used.
+        #[arg(short = 'j', long = "jobs", value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: Option<u16>,
+        /// The output format of the downloaded volumes, defaults to the `format` setting or `folder`
+        #[arg(short = 'f', long = "format", value_enum)]
+        format: Option<DownloadFormat>,
+        /// Package each episode into its own archive
+        ///
+        /// With the `folder` format, this is the same as `--layout episode` unless a layout is set.
+        #[arg(long = "per-episode")]
+        per_episode: bool,
+        /// How the pages are laid out inside the volume directory, defaults to the `layout` setting or `flat`
+        #[arg(short = 'l', long = "layout", value_enum)]
+        layout: Option<DownloadLayout>,
+        /// Whether to download the volume cover, defaults to the `cover` setting or `auto`
+        #[arg(long = "cover", value_enum)]
+        cover: Option<CoverMode>,
+        /// The root directory for the downloads, defaults to the `output` setting or `DOWNLOADS`
+        #[arg(short = 'o', long = "output")]
+        output: Option<std::path::PathBuf>,
+        /// The template for each volume directory inside the output
+        ///
+        /// Available variables: `{title}`, `{slug}`, `{volume}`, and `{volume_name}`.
+        /// Numbers can be zero padded like `{volume:02}`, and `/` creates nested directories.
+        /// It must contain `{volume}` so each volume gets its own directory.
+        ///
+        /// Defaults to the `dir_template` setting or `{slug}/v{volume:02}`.
+        #[arg(long = "dir-template")]
+        dir_template: Option<String>,
+        /// The template for each page filename inside the volume directory
+        ///
+        /// Accepts the same variables as `--dir-template` plus `{episode}`, `{page}`, and `{ext}`.
+        /// It must contain `{page}` and `{ext}`.
+        ///
+        /// Defaults to the `file_template` setting or `p{page:03}.{ext}`.
+        #[arg(long = "file-template")]
+        file_template: Option<String>,
+        /// Re-encode each page after decrypting it
+        ///
+        /// `png` and `webp` are lossless, `original` keeps the original format.
+        #[arg(long = "transcode", value_enum)]
+        transcode: Option<TranscodeFormat>,
+        /// The JPEG quality from 1 to 100 when transcoding, defaults to 90
+        ///
+        /// Only valid with `--transcode`, `--grayscale`, or the device options.
+        #[arg(long = "quality", value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: Option<u8>,
+        /// Convert each page to grayscale, implies `--transcode original` if not set
+        #[arg(long = "grayscale")]
+        grayscale: bool,
+        /// Resize the pages to fit the e-reader screen and adjust the gamma for it
+        ///
+        /// E-ink devices also convert the pages to grayscale.
+        #[arg(long = "device", value_parser = clap::builder::PossibleValuesParser::new(DEVICE_PROFILES.map(|p| p.name)))]
+        device: Option<String>,
+        /// Trim the uniform margins around each page
+        #[arg(long = "trim")]
+        trim: bool,
+        /// Split landscape double-page spreads into two pages in the given reading order
+        #[arg(long = "split", value_enum)]
+        split: Option<SpreadOrder>,
+        /// Detect the double-page spreads and merge them into a single right-to-left image
+        ///
+        /// `alongside` keeps both pages and adds the spread after them, `replace` uses the spread
+        /// instead of the pages when packaging. The pages are always kept in the volume directory.
+        #[arg(long = "spreads", value_enum, conflicts_with = "split")]
+        spreads: Option<SpreadMerge>,
+        /// The gamma correction, values above 1.0 darken the midtones
+        ///
+        /// Defaults to the `--device` gamma or 1.0.
+        #[arg(long = "gamma")]
+        gamma: Option<f32>,
+        /// The contrast adjustment in percent, negative values reduce the contrast
+        #[arg(long = "contrast", allow_negative_numbers = true)]
+        contrast: Option<f32>,
+        /// Save the pages without decrypting them, use `emancipated decrypt` to decrypt them later
+        ///
+        /// The pages can not be processed or packaged until they are decrypted.
+        #[arg(
+            long = "keep-encrypted",
+            conflicts_with_all = ["transcode", "grayscale", "device", "trim", "split", "spreads", "gamma", "contrast"]
+        )]
+        keep_encrypted: bool,
+        /// How many times to retry a failed page download, defaults to the `retries` setting or 3
+        #[arg(long = "retries")]
+        retries: Option<u32>,
+        /// The base delay in milliseconds before retrying, doubled on each retry
+        ///
+        /// Defaults to the `retry_delay` setting or 500ms.
+        #[arg(long = "retry-delay")]
+        retry_delay: Option<u64>,
+    },
+    /// Export an already downloaded volume into another format
+    Export {
+        /// Path to the downloaded volume directory
+        path: std::path::PathBuf,
+        /// The output format
+        #[arg(short = 'f', long = "format", value_enum)]
+        format: DownloadFormat,
+        /// Package each episode into its own archive (only for `cbz` format)
+        #[arg(long = "per-episode")]
+        per_episode: bool,
+    },
+    /// Decrypt a volume downloaded with `--keep-encrypted`
+    ///
+    /// This does not need network access, only the private key of the account used to download it.
+    Decrypt {
+        /// Path to the downloaded volume directory
+        path: std::path::PathBuf,
+        /// Package the volume after decrypting it
+        #[arg(short = 'f', long = "format", value_enum)]
+        format: Option<DownloadFormat>,
+        /// Package each episode into its own archive (only for `cbz` format)
+        #[arg(long = "per-episode")]
+        per_episode: bool,
+    },
+    /// See and change the settings
+    ///
+    /// Use `--account` to change the settings for a specific account only.
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+    /// Check the accounts, keys, and settings for problems
+    Doctor {
+        /// Repair the problems, broken files are moved to the `quarantine` folder
+        #[arg(long)]
+        fix: bool,
     },
     /// Get a title information including all the available volumes
     Info {
@@ -72,6 +304,180 @@ pub(crate) enum EmancipatedCommands {
     },
 }
 
+/// The output format of the downloaded volumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum DownloadFormat {
+    /// Keep the loose images in a folder
+    Folder,
+    /// Package the images into a CBZ archive with `ComicInfo.xml`
+    Cbz,
+    /// Build a fixed-layout EPUB 3
+    Epub,
+    /// Assemble the images into a single PDF
+    Pdf,
+}
+
+/// How the pages are laid out inside the volume directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum DownloadLayout {
+    /// All the pages in the volume directory, numbered across the whole volume
+    Flat,
+    /// Each episode in its own `eNNN` directory, numbered from 1 in each episode
+    Episode,
+}
+
+/// Whether to download the volume cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum CoverMode {
+    /// Skip the cover when the official reader does, since the first page is already the cover
+    Auto,
+    /// Always download the cover
+    Keep,
+    /// Never download the cover
+    Skip,
+}
+
+/// The format to re-encode the pages into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TranscodeFormat {
+    /// Keep the original format
+    Original,
+    /// Lossless PNG
+    Png,
+    /// JPEG with the `--quality`
+    Jpeg,
+    /// Lossless WebP
+    Webp,
+}
+
+impl From<TranscodeFormat> for OutputFormat {
+    fn from(format: TranscodeFormat) -> Self {
+        match format {
+            TranscodeFormat::Original => OutputFormat::Original,
+            TranscodeFormat::Png => OutputFormat::Png,
+            TranscodeFormat::Jpeg => OutputFormat::Jpeg,
+            TranscodeFormat::Webp => OutputFormat::Webp,
+        }
+    }
+}
+
+/// The reading order of a split spread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SpreadOrder {
+    /// The right half first, the usual order for manga
+    Rtl,
+    /// The left half first
+    Ltr,
+}
+
+impl From<SpreadOrder> for SplitOrder {
+    fn from(order: SpreadOrder) -> Self {
+        match order {
+            SpreadOrder::Rtl => SplitOrder::RightToLeft,
+            SpreadOrder::Ltr => SplitOrder::LeftToRight,
+        }
+    }
+}
+
+/// How the merged double-page spreads are used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SpreadMerge {
+    /// Add the spread after the two pages
+    Alongside,
+    /// Use the spread instead of the two pages
+    Replace,
+}
+
+impl From<SpreadMerge> for SpreadMode {
+    fn from(mode: SpreadMerge) -> Self {
+        match mode {
+            SpreadMerge::Alongside => SpreadMode::Alongside,
+            SpreadMerge::Replace => SpreadMode::Replace,
+        }
+    }
+}
+
+/// A single volume range in the selector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VolumeRange {
+    /// A single volume, e.g. `5`
+    Single(i32),
+    /// An inclusive range of volumes, e.g. `1-5`
+    Range(i32, i32),
+    /// An open range of volumes, e.g. `10-`
+    From(i32),
+}
+
+impl VolumeRange {
+    /// Check if the volume number is included in this range
+    pub(crate) fn contains(&self, number: i32) -> bool {
+        match self {
+            VolumeRange::Single(single) => *single == number,
+            VolumeRange::Range(start, end) => (*start..=*end).contains(&number),
+            VolumeRange::From(start) => number >= *start,
+        }
+    }
+}
+
+/// The volume selector used for the download command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VolumeSelector {
+    /// All the purchased volumes
+    All,
+    /// The latest available volume
+    Latest,
+    /// A list of volume or volume ranges
+    Ranges(Vec<VolumeRange>),
+}
+
+impl std::str::FromStr for VolumeSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "all" | "purchased" => return Ok(VolumeSelector::All),
+            "latest" | "last" => return Ok(VolumeSelector::Latest),
+            _ => {}
+        }
+
+        let parse_number = |num: &str| {
+            num.trim()
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid volume number: `{}`", num.trim()))
+        };
+
+        let mut ranges = vec![];
+        for part in s.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+
+            let range = match part.split_once('-') {
+                Some((start, "")) => VolumeRange::From(parse_number(start)?),
+                Some((start, end)) => {
+                    let start = parse_number(start)?;
+                    let end = parse_number(end)?;
+                    if start > end {
+                        return Err(format!("Invalid volume range: `{}`", part));
+                    }
+                    VolumeRange::Range(start, end)
+                }
+                None => VolumeRange::Single(parse_number(part)?),
+            };
+
+            ranges.push(range);
+        }
+
+        if ranges.is_empty() {
+            return Err("No volume specified".to_string());
+        }
+
+        Ok(VolumeSelector::Ranges(ranges))
+    }
+}
+
 fn cli_styles() -> Styles {
     Styles::styled()
         .header(AnsiColor::Green.on_default() | Effects::BOLD)
@@ -79,3 +485,77 @@ fn cli_styles() -> Styles {
         .literal(AnsiColor::Blue.on_default() | Effects::BOLD)
         .placeholder(AnsiColor::BrightCyan.on_default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(selector: &str) -> Result<VolumeSelector, String> {
+        selector.parse()
+    }
+
+    #[test]
+    fn test_volume_selector_keywords() {
+        assert_eq!(parse("all"), Ok(VolumeSelector::All));
+        assert_eq!(parse(" Purchased "), Ok(VolumeSelector::All));
+        assert_eq!(parse("latest"), Ok(VolumeSelector::Latest));
+        assert_eq!(parse("LAST"), Ok(VolumeSelector::Latest));
+    }
+
+    #[test]
+    fn test_volume_selector_ranges() {
+        assert_eq!(
+            parse("5"),
+            Ok(VolumeSelector::Ranges(vec![VolumeRange::Single(5)]))
+        );
+        assert_eq!(
+            parse("0"),
+            Ok(VolumeSelector::Ranges(vec![VolumeRange::Single(0)]))
+        );
+        assert_eq!(
+            parse("1-3, 7,10-"),
+            Ok(VolumeSelector::Ranges(vec![
+                VolumeRange::Range(1, 3),
+                VolumeRange::Single(7),
+                VolumeRange::From(10),
+            ]))
+        );
+        // Overlapping ranges are kept as is, the volumes are deduplicated when resolved
+        assert_eq!(
+            parse("1-3,2-4,,"),
+            Ok(VolumeSelector::Ranges(vec![
+                VolumeRange::Range(1, 3),
+                VolumeRange::Range(2, 4),
+            ]))
+        );
+        assert_eq!(
+            parse("2-2"),
+            Ok(VolumeSelector::Ranges(vec![VolumeRange::Range(2, 2)]))
+        );
+    }
+
+    #[test]
+    fn test_volume_selector_invalid() {
+        assert_eq!(parse("5-1"), Err("Invalid volume range: `5-1`".to_string()));
+        assert_eq!(
+            parse("one"),
+            Err("Invalid volume number: `one`".to_string())
+        );
+        assert_eq!(parse("-3"), Err("Invalid volume number: ``".to_string()));
+        assert_eq!(
+            parse("1-2-3"),
+            Err("Invalid volume number: `2-3`".to_string())
+        );
+        assert_eq!(parse(" , "), Err("No volume specified".to_string()));
+    }
+
+    #[test]
+    fn test_volume_range_contains() {
+        assert!(VolumeRange::Single(2).contains(2));
+        assert!(!VolumeRange::Single(2).contains(3));
+        assert!(VolumeRange::Range(1, 3).contains(3));
+        assert!(!VolumeRange::Range(1, 3).contains(4));
+        assert!(VolumeRange::From(10).contains(99));
+        assert!(!VolumeRange::From(10).contains(9));
+    }
+}
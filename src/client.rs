@@ -3,17 +3,21 @@ use serde_json::json;
 use std::{collections::HashMap, fmt::Debug, sync::LazyLock};
 
 use crate::{
-    config::{google_auth::VerifyPasswordResponseMinimal, save_config, Config},
+    cassette::{Cassette, CassetteError},
+    config::{
+        google_auth::VerifyPasswordResponseMinimal, save_config, Config, ConfigError, Passphrase,
+    },
     image::ImageError,
     kp::{self, RSAError},
     models::{
-        Comic, ComicContents, ComicVolumes, GraphQLResponse, GraphQLResponseError, UserInfoQuery,
+        Comic, ComicContents, ComicVolumes, GraphQLResponse, GraphQLResponseError, PurchasedComic,
+        UserInfoQuery,
     },
 };
 
 const FF_UA: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:130.0) Gecko/20100101 Firefox/130.0";
-pub(crate) static BASE_HOST: LazyLock<String> = LazyLock::new(|| {
+pub static BASE_HOST: LazyLock<String> = LazyLock::new(|| {
     let decoded = general_purpose::STANDARD
         .decode("ZW1hcWkuY29t")
         .expect("Failed to decode BASE_HOST");
@@ -43,6 +47,62 @@ static TOKEN_AUTH: LazyLock<String> = LazyLock::new(|| {
     String::from_utf8(decoded).expect("Failed to convert TOKEN_AUTH to String")
 });
 
+/// The endpoints used by the [`Client`]
+///
+/// By default this points to the actual API, but it can be changed to point to a local server for testing.
+#[derive(Debug, Clone)]
+pub struct ClientEndpoints {
+    /// The GraphQL API URL
+    pub api: String,
+    /// The `Host` header used for the GraphQL API
+    pub api_host: String,
+    /// The URL used to login with email and password
+    pub identity_toolkit: String,
+    /// The URL used to refresh the token
+    pub secure_token: String,
+}
+
+impl Default for ClientEndpoints {
+    fn default() -> Self {
+        Self {
+            api: API_URL.to_string(),
+            api_host: API_HOST.to_string(),
+            identity_toolkit:
+                "https://identitytoolkit.googleapis.com/v1/accounts:signInWithPassword".to_string(),
+            secure_token: "https://securetoken.googleapis.com/v1/token".to_string(),
+        }
+    }
+}
+
+impl ClientEndpoints {
+    /// Create the endpoints with every service served from the same base URL
+    ///
+    /// The base URL should not have a trailing slash, e.g. `http://127.0.0.1:8080`.
+    /// - GraphQL API: `{base}/graphql`
+    /// - Login: `{base}/v1/accounts:signInWithPassword`
+    /// - Token refresh: `{base}/v1/token`
+    pub fn from_base_url(base_url: impl Into<String>) -> Self {
+        let base_url: String = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let api_host = reqwest::Url::parse(base_url)
+            .ok()
+            .and_then(|url| {
+                url.host_str().map(|host| match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                })
+            })
+            .unwrap_or_else(|| API_HOST.to_string());
+
+        Self {
+            api: format!("{}/graphql", base_url),
+            api_host,
+            identity_toolkit: format!("{}/v1/accounts:signInWithPassword", base_url),
+            secure_token: format!("{}/v1/token", base_url),
+        }
+    }
+}
+
 /// Error type that happens when parsing the response from the API
 ///
 /// This is specifically for [`serde`] errors.
@@ -87,21 +147,40 @@ impl DetailedSerdeError {
         let start_idx = position.saturating_sub(25);
         let end_idx = position.saturating_add(25);
 
-        // Bound the start and end index
-        let start_idx = start_idx.max(0);
+        // Bound the end index
         let end_idx = end_idx.min(split_lines[row_line].len());
 
         split_lines[row_line][start_idx..end_idx].to_string()
     }
 }
 
+/// The main error type for the [`Client`]
 pub enum ClientError {
     Reqwest(reqwest::Error),
     Serde(serde_json::Error),
-    DetailedSerde(DetailedSerdeError),
+    DetailedSerde(Box<DetailedSerdeError>),
     RSA(RSAError),
     Image(ImageError),
     GraphQLError(GraphQLResponseError),
+    Cassette(CassetteError),
+    Config(ConfigError),
+}
+
+impl ClientError {
+    /// Check if the error is transient and the request can be retried
+    ///
+    /// This includes server errors (5xx), rate limiting, timeouts, and connection errors.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => match e.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            },
+            _ => false,
+        }
+    }
 }
 
 impl From<reqwest::Error> for ClientError {
@@ -128,9 +207,21 @@ impl From<ImageError> for ClientError {
     }
 }
 
+impl From<CassetteError> for ClientError {
+    fn from(e: CassetteError) -> Self {
+        Self::Cassette(e)
+    }
+}
+
+impl From<ConfigError> for ClientError {
+    fn from(e: ConfigError) -> Self {
+        Self::Config(e)
+    }
+}
+
 impl From<DetailedSerdeError> for ClientError {
     fn from(e: DetailedSerdeError) -> Self {
-        Self::DetailedSerde(e)
+        Self::DetailedSerde(Box::new(e))
     }
 }
 
@@ -142,6 +233,8 @@ impl std::fmt::Display for ClientError {
             Self::RSA(e) => write!(f, "RSA Error: {}", e),
             Self::Image(e) => write!(f, "Image Error: {}", e),
             Self::GraphQLError(e) => write!(f, "GraphQL Error: {}", e),
+            Self::Cassette(e) => write!(f, "Cassette Error: {}", e),
+            Self::Config(e) => write!(f, "Config Error: {}", e),
             Self::DetailedSerde(e) => write!(
                 f,
                 "Serde Error: {}\nStatus Code: {}\nHeaders: {:?}\nURL: {}\nJSON excerpt: {}",
@@ -163,6 +256,8 @@ impl std::fmt::Debug for ClientError {
             Self::RSA(e) => write!(f, "RSA Error: {}", e),
             Self::Image(e) => write!(f, "Image Error: {}", e),
             Self::GraphQLError(e) => write!(f, "GraphQL Error: {}", e),
+            Self::Cassette(e) => write!(f, "Cassette Error: {}", e),
+            Self::Config(e) => write!(f, "Config Error: {}", e),
             Self::DetailedSerde(e) => write!(
                 f,
                 "Serde Error: {}\nStatus Code: {}\nHeaders: {:?}\nURL: {}\nJSON excerpt: {}",
@@ -176,50 +271,92 @@ impl std::fmt::Debug for ClientError {
     }
 }
 
+/// The main client to interact with the API
 #[derive(Debug, Clone)]
 pub struct Client {
     client: reqwest::Client,
+    /// Client without the API headers, used for the CDN and token refresh
+    cdn_client: reqwest::Client,
+    endpoints: ClientEndpoints,
+    cassette: Option<Cassette>,
     config: Config,
+    /// Used to unlock the key pair and save the refreshed tokens
+    passphrase: Option<Passphrase>,
     priv_key: rsa::RsaPrivateKey,
     pub_key: rsa::RsaPublicKey,
 }
 
 impl Client {
-    pub fn new(config: &mut Config, proxy: Option<reqwest::Proxy>) -> Result<Self, ClientError> {
+    /// Create a new client, the passphrase is required if the accounts are encrypted
+    pub fn new(
+        config: &mut Config,
+        passphrase: Option<Passphrase>,
+        proxy: Option<reqwest::Proxy>,
+    ) -> Result<Self, ClientError> {
+        Self::new_with_endpoints(config, passphrase, proxy, ClientEndpoints::default())
+    }
+
+    /// Create a new client with custom endpoints
+    pub fn new_with_endpoints(
+        config: &mut Config,
+        passphrase: Option<Passphrase>,
+        proxy: Option<reqwest::Proxy>,
+        endpoints: ClientEndpoints,
+    ) -> Result<Self, ClientError> {
+        let api_host = reqwest::header::HeaderValue::from_str(&endpoints.api_host)
+            .unwrap_or_else(|_| reqwest::header::HeaderValue::from_static(&API_HOST));
+
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             reqwest::header::USER_AGENT,
             reqwest::header::HeaderValue::from_static(FF_UA),
         );
-        headers.insert(
-            reqwest::header::HOST,
-            reqwest::header::HeaderValue::from_static(&API_HOST),
-        );
+        headers.insert(reqwest::header::HOST, api_host);
 
         let client = reqwest::Client::builder()
             .http2_adaptive_window(true)
             .default_headers(headers);
 
-        let client = match proxy {
-            Some(proxy) => client.proxy(proxy),
+        let client = match &proxy {
+            Some(proxy) => client.proxy(proxy.clone()),
             None => client,
         }
         .build()?;
 
+        let cdn_client = reqwest::Client::builder()
+            .http2_adaptive_window(true)
+            .user_agent(FF_UA);
+
+        let cdn_client = match proxy {
+            Some(proxy) => cdn_client.proxy(proxy),
+            None => cdn_client,
+        }
+        .build()?;
+
         let (priv_key, pub_key) = if !config.has_key() {
-            config.generate_key_pair()?
+            config.generate_key_pair(passphrase.as_ref())?
         } else {
-            config.get_key_pair()?
+            config.get_key_pair(passphrase.as_ref())?
         };
 
         Ok(Self {
             client,
+            cdn_client,
+            endpoints,
+            cassette: None,
             config: config.clone(),
+            passphrase,
             priv_key,
             pub_key,
         })
     }
 
+    /// Record or replay all the GraphQL and CDN requests with the cassette
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
     /// Get the configuration of the client.
     pub fn get_config_owned(&self) -> Config {
         self.config.clone()
@@ -229,6 +366,11 @@ impl Client {
         &self.config
     }
 
+    /// Get the passphrase used to unlock the account, if any
+    pub fn get_passphrase(&self) -> Option<&Passphrase> {
+        self.passphrase.as_ref()
+    }
+
     pub fn get_private_key(&self) -> &rsa::RsaPrivateKey {
         &self.priv_key
     }
@@ -241,7 +383,8 @@ impl Client {
     /// if the token is expired.
     pub async fn refresh_token(&mut self) -> Result<(), ClientError> {
         // If the expiry time is set and it's not expired, return early
-        if !self.config.is_expired() {
+        // The token is never used when replaying, so there is nothing to refresh
+        if !self.config.is_expired() || self.cassette.as_ref().is_some_and(|c| c.is_replay()) {
             return Ok(());
         }
 
@@ -250,11 +393,9 @@ impl Client {
             "refreshToken": self.config.refresh_token(),
         });
 
-        let client = reqwest::Client::builder()
-            .http2_adaptive_window(true)
-            .build()?;
-        let request = client
-            .post("https://securetoken.googleapis.com/v1/token")
+        let request = self
+            .cdn_client
+            .post(&self.endpoints.secure_token)
             .header(reqwest::header::USER_AGENT, FF_UA)
             .query(&[("key", TOKEN_AUTH.to_string())])
             .json(&json_data)
@@ -269,7 +410,7 @@ impl Client {
         self.config.set_refresh_token(&response.refresh_token);
         self.config.set_expires_at(response.expires_at());
 
-        save_config(&self.config);
+        save_config(&self.config, self.passphrase.as_ref())?;
 
         Ok(())
     }
@@ -293,39 +434,18 @@ impl Client {
 
         let req = self
             .client
-            .post(&*API_URL)
+            .post(&self.endpoints.api)
             .header(reqwest::header::USER_AGENT, FF_UA)
-            .header(reqwest::header::HOST, API_HOST.to_string())
+            .header(reqwest::header::HOST, &self.endpoints.api_host)
             .header(reqwest::header::CONTENT_TYPE, "application/json")
             .header("x-hash", x_hash)
             .header(
                 reqwest::header::AUTHORIZATION,
                 format!("Bearer {}", self.config.access_token()),
             )
-            .json(&json_data)
-            .send()
-            .await?
-            // since all graphql requests will be 200 OK, we need to check the response
-            .error_for_status()?;
-
-        let status_code = req.status();
-        let headers = req.headers().clone();
-        let url = req.url().clone();
-        let text_data = req.text().await?;
-
-        // Check for errors
-        match serde_json::from_str::<GraphQLResponseError>(&text_data) {
-            Ok(error_response) => {
-                return Err(ClientError::GraphQLError(error_response));
-            }
-            Err(_) => {}
-        }
-
-        // Actual response
-        let resp = serde_json::from_str::<GraphQLResponse<T>>(&text_data)
-            .map_err(|e| DetailedSerdeError::new(e, status_code, &headers, &url, text_data))?;
+            .json(&json_data);
 
-        Ok(resp)
+        self.send_graphql(req, &json_data).await
     }
 
     async fn query<T>(
@@ -345,31 +465,87 @@ impl Client {
 
         let req = self
             .client
-            .post(&*API_URL)
+            .post(&self.endpoints.api)
             .header(reqwest::header::USER_AGENT, FF_UA)
-            .header(reqwest::header::HOST, API_HOST.to_string())
+            .header(reqwest::header::HOST, &self.endpoints.api_host)
             .header(reqwest::header::CONTENT_TYPE, "application/json")
             .header(
                 reqwest::header::AUTHORIZATION,
                 format!("Bearer {}", self.config.access_token()),
             )
-            .json(&json_data)
-            .send()
-            .await?
-            // since all graphql requests will be 200 OK, we need to check the response
-            .error_for_status()?;
+            .json(&json_data);
 
-        let status_code = req.status();
-        let headers = req.headers().clone();
-        let url = req.url().clone();
-        let text_data = req.text().await?;
+        self.send_graphql(req, &json_data).await
+    }
 
-        // Check for errors
-        match serde_json::from_str::<GraphQLResponseError>(&text_data) {
-            Ok(error_response) => {
-                return Err(ClientError::GraphQLError(error_response));
+    /// Send the GraphQL request and parse the response
+    ///
+    /// When a cassette is attached, the exchange is either recorded or served from the cassette.
+    async fn send_graphql<T>(
+        &self,
+        request: reqwest::RequestBuilder,
+        json_data: &serde_json::Value,
+    ) -> Result<GraphQLResponse<T>, ClientError>
+    where
+        T: serde::de::DeserializeOwned + Debug + Clone,
+    {
+        let (status_code, headers, url, text_data) = match &self.cassette {
+            Some(cassette) if cassette.is_replay() => {
+                let entry = cassette.replay_graphql(json_data)?;
+                let mut headers = reqwest::header::HeaderMap::new();
+                if let Some(content_type) = entry
+                    .content_type
+                    .as_deref()
+                    .and_then(|c| reqwest::header::HeaderValue::from_str(c).ok())
+                {
+                    headers.insert(reqwest::header::CONTENT_TYPE, content_type);
+                }
+                let status_code =
+                    reqwest::StatusCode::from_u16(entry.status).unwrap_or(reqwest::StatusCode::OK);
+                let url = reqwest::Url::parse(&entry.url)
+                    .unwrap_or_else(|_| reqwest::Url::parse(&self.endpoints.api).unwrap());
+
+                (status_code, headers, url, entry.response_text())
+            }
+            _ => {
+                let request = request.build()?;
+                let request_headers = request.headers().clone();
+
+                let req = self.client.execute(request).await?;
+                // since all graphql requests will be 200 OK, we need to check the response
+                // but only after recording it, so the failures can be replayed too
+                let status_error = req.error_for_status_ref().err();
+
+                let status_code = req.status();
+                let headers = req.headers().clone();
+                let url = req.url().clone();
+                let text_data = req.text().await?;
+
+                if let Some(cassette) = &self.cassette {
+                    let content_type = headers
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|c| c.to_str().ok());
+                    cassette.record_graphql(
+                        &url,
+                        &request_headers,
+                        json_data,
+                        status_code,
+                        content_type,
+                        &text_data,
+                    )?;
+                }
+
+                if let Some(error) = status_error {
+                    return Err(error.into());
+                }
+
+                (status_code, headers, url, text_data)
             }
-            Err(_) => {}
+        };
+
+        // Check for errors
+        if let Ok(error_response) = serde_json::from_str::<GraphQLResponseError>(&text_data) {
+            return Err(ClientError::GraphQLError(error_response));
         }
 
         // Actual response
@@ -537,20 +713,64 @@ impl Client {
         Ok(response.data)
     }
 
+    /// Get all the titles and volumes the user has purchased
+    pub async fn get_purchased(&mut self) -> Result<Vec<PurchasedComic>, ClientError> {
+        let query = r#"query getUserLibrary {
+            userLibrary {
+                comic {
+                    comicId
+                    slug
+                    title
+                    cover {
+                        url
+                        height
+                    }
+                    noVolume
+                    genres {
+                        name
+                        tagId
+                    }
+                    metadata {
+                        completed
+                        creators
+                    }
+                }
+                volumes {
+                    slug
+                    volumeNumber
+                    name
+                }
+            }
+        }"#;
+
+        let response = self
+            .query::<crate::models::PurchasedQuery>(query, HashMap::new())
+            .await?;
+
+        Ok(response.data.library)
+    }
+
     pub async fn download_image(&self, url: impl Into<String>) -> Result<Vec<u8>, ClientError> {
         let url_s: String = url.into();
 
-        // New client
-        let client = reqwest::Client::builder()
-            .http2_adaptive_window(true)
-            .user_agent(FF_UA)
-            .build()?;
+        if let Some(cassette) = self.cassette.as_ref().filter(|c| c.is_replay()) {
+            return Ok(cassette.replay_cdn(&url_s)?);
+        }
 
-        let request = client.get(&url_s).send().await?.error_for_status()?;
+        let request = self
+            .cdn_client
+            .get(&url_s)
+            .send()
+            .await?
+            .error_for_status()?;
 
         // Get the bytes
         let bytes = request.bytes().await?;
 
+        if let Some(cassette) = &self.cassette {
+            cassette.record_cdn(&url_s, &bytes)?;
+        }
+
         // Return the decrypted bytes
         Ok(bytes.to_vec())
     }
@@ -559,6 +779,16 @@ impl Client {
         email: impl Into<String>,
         password: impl Into<String>,
         proxy: Option<reqwest::Proxy>,
+    ) -> Result<VerifyPasswordResponseMinimal, ClientError> {
+        Self::login_with_endpoints(email, password, proxy, &ClientEndpoints::default()).await
+    }
+
+    /// Login with custom endpoints
+    pub async fn login_with_endpoints(
+        email: impl Into<String>,
+        password: impl Into<String>,
+        proxy: Option<reqwest::Proxy>,
+        endpoints: &ClientEndpoints,
     ) -> Result<VerifyPasswordResponseMinimal, ClientError> {
         let email_s: String = email.into();
         let password_s: String = password.into();
@@ -577,7 +807,7 @@ impl Client {
         }
         .build()?;
         let request = client
-            .post("https://identitytoolkit.googleapis.com/v1/accounts:signInWithPassword")
+            .post(&endpoints.identity_toolkit)
             .header(reqwest::header::USER_AGENT, FF_UA)
             .query(&[("key", TOKEN_AUTH.to_string())])
             .json(&json_data)
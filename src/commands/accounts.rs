@@ -1,29 +1,111 @@
 use color_print::cformat;
 
-use crate::{cli::ExitCode, client::Client, config::save_config};
+use emancipated::{
+    config::{
+        change_passphrase, get_config, get_config_path, get_default_account, has_encrypted_config,
+        remove_config, save_config, scan_configs, set_default_account, unlock, Config, ConfigError,
+        Passphrase,
+    },
+    Client,
+};
+
+use crate::{
+    cli::{ExitCode, PasswordArgs},
+    term::ConsoleChoice,
+};
+
+/// Environment variable to read the password from, mainly for CI
+pub(crate) const PASSWORD_ENV: &str = "EMANCIPATED_PASSWORD";
+/// Environment variable to read the passphrase for the encrypted accounts from
+pub(crate) const PASSPHRASE_ENV: &str = "EMANCIPATED_PASSPHRASE";
+
+/// Get the passphrase from the environment variable, ignoring it when empty
+fn env_passphrase() -> Option<String> {
+    std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty())
+}
+
+/// Take the first line of the input as the password
+fn first_line(input: &str) -> String {
+    input.lines().next().unwrap_or_default().to_string()
+}
+
+/// Resolve the password in order of: argument, file, stdin, environment variable, and interactive prompt
+fn resolve_password(args: &PasswordArgs, console: &crate::term::Terminal) -> Option<String> {
+    if let Some(password) = &args.password {
+        console.warn(
+            "Passing the password as an argument is insecure, it will be visible in your shell history!",
+        );
+        return Some(password.clone());
+    }
+
+    if let Some(path) = &args.password_file {
+        return match std::fs::read_to_string(path) {
+            Ok(content) => Some(first_line(&content)),
+            Err(e) => {
+                console.error(cformat!(
+                    "Failed to read password file <m,s>{}</>: <r,s>{}</>",
+                    path.display(),
+                    e
+                ));
+                None
+            }
+        };
+    }
+
+    if args.password_stdin {
+        let mut content = String::new();
+        return match std::io::stdin().read_line(&mut content) {
+            Ok(_) => Some(first_line(&content)),
+            Err(e) => {
+                console.error(cformat!(
+                    "Failed to read password from stdin: <r,s>{}</>",
+                    e
+                ));
+                None
+            }
+        };
+    }
+
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Some(password);
+    }
+
+    console.password("Password:")
+}
 
 pub(crate) async fn accounts_auth(
     email: impl Into<String>,
-    password: impl Into<String>,
+    password: &PasswordArgs,
+    passphrase: Option<&Passphrase>,
     proxy: Option<reqwest::Proxy>,
     console: &crate::term::Terminal,
 ) -> ExitCode {
     let email: String = email.into();
+    let password = match resolve_password(password, console) {
+        Some(password) if !password.is_empty() => password,
+        _ => {
+            console.error("No password provided!");
+            return 1;
+        }
+    };
+
     console.info(cformat!("Logging in as <m,s>{}</>...", &email));
-    match crate::client::Client::login(&email, password, proxy).await {
+    match Client::login(&email, password, proxy).await {
         Ok(account) => {
-            let mut config = crate::config::Config::from(&account);
+            let mut config = Config::from(&account);
 
             console.info(cformat!("Logged in as <m,s>{}</>", account.email));
 
-            match config.generate_key_pair() {
-                Ok(_) => {
-                    console.info("Generated RSA key pair, saving config...");
-                    save_config(&config);
-                    0
-                }
+            if let Err(e) = config.generate_key_pair(passphrase) {
+                console.error(cformat!("Failed to generate RSA key pair: <r,s>{}</>", e));
+                return 1;
+            }
+
+            console.info("Generated RSA key pair, saving config...");
+            match save_config(&config, passphrase) {
+                Ok(_) => 0,
                 Err(e) => {
-                    console.error(cformat!("Failed to generate RSA key pair: <r,s>{}</>", e));
+                    console.error(cformat!("Failed to save config: <r,s>{}</>", e));
                     1
                 }
             }
@@ -35,6 +117,13 @@ pub(crate) async fn accounts_auth(
     }
 }
 
+/// Save the possibly refreshed tokens of the client, only warn on failure
+pub(crate) fn save_client_config(client: &Client, console: &crate::term::Terminal) {
+    if let Err(e) = save_config(client.get_config(), client.get_passphrase()) {
+        console.warn(cformat!("Failed to save config: <r,s>{}</>", e));
+    }
+}
+
 pub(crate) async fn accounts_info(
     client: &mut Client,
     console: &crate::term::Terminal,
@@ -47,7 +136,7 @@ pub(crate) async fn accounts_info(
 
     match client.get_user().await {
         Ok(user_info) => {
-            save_config(client.get_config());
+            save_client_config(client, console);
 
             console.info(cformat!("Account info for <m,s>{}</>:", account.email()));
             console.info(cformat!("  - <s>ID</>: {}", user_info.user.id));
@@ -76,8 +165,50 @@ pub(crate) async fn accounts_info(
     }
 }
 
-pub(crate) async fn accounts_all(console: &crate::term::Terminal) -> ExitCode {
-    let all_configs = crate::config::find_any_config();
+/// Load all the valid accounts, warning about the broken ones
+pub(crate) fn load_accounts(
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> Option<Vec<Config>> {
+    let files = match scan_configs(passphrase) {
+        Ok(files) => files,
+        Err(e) => {
+            console.error(cformat!("Failed to read accounts: <r,s>{}</>", e));
+            return None;
+        }
+    };
+
+    let mut configs = vec![];
+    let mut broken = 0;
+    for file in files {
+        match file.config {
+            Ok(config) => configs.push(config),
+            // Already reported when unlocking
+            Err(ConfigError::Locked(_)) => {}
+            Err(e) => {
+                console.warn(format!("Skipping broken account: {}", e));
+                broken += 1;
+            }
+        }
+    }
+
+    if broken > 0 {
+        console.warn(cformat!(
+            "Found <s>{}</> broken accounts, run <m,s>emancipated doctor --fix</> to repair them",
+            broken
+        ));
+    }
+
+    Some(configs)
+}
+
+pub(crate) async fn accounts_all(
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    let Some(all_configs) = load_accounts(passphrase, console) else {
+        return 1;
+    };
 
     match all_configs.len() {
         0 => {
@@ -86,12 +217,325 @@ pub(crate) async fn accounts_all(console: &crate::term::Terminal) -> ExitCode {
             1
         }
         _ => {
+            let default_account = get_default_account();
+
             console.info(cformat!("Found {} accounts:", all_configs.len()));
             for (i, c) in all_configs.iter().enumerate() {
-                console.info(cformat!("{:02}. <s>{}</>", i + 1, c.email()));
+                if default_account.as_deref() == Some(c.email()) {
+                    console.info(cformat!(
+                        "{:02}. <s>{}</> <g,s>(default)</>",
+                        i + 1,
+                        c.email()
+                    ));
+                } else {
+                    console.info(cformat!("{:02}. <s>{}</>", i + 1, c.email()));
+                }
+            }
+
+            0
+        }
+    }
+}
+
+/// Check that the account exists, reporting why if not
+fn account_exists(
+    email: &str,
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> bool {
+    match get_config(email, passphrase) {
+        Ok(Some(_)) => true,
+        Ok(None) => {
+            console.warn(cformat!("Account <m,s>{}</> not found!", email));
+            false
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to read account: <r,s>{}</>", e));
+            false
+        }
+    }
+}
+
+pub(crate) fn accounts_remove(email: &str, yes: bool, console: &crate::term::Terminal) -> ExitCode {
+    // Not loaded, so the locked and broken accounts can be removed too
+    match get_config_path(email) {
+        Ok(path) if path.exists() => {}
+        Ok(_) => {
+            console.warn(cformat!("Account <m,s>{}</> not found!", email));
+            return 1;
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to find account: <r,s>{}</>", e));
+            return 1;
+        }
+    }
+
+    if !yes && !console.confirm(&format!("Remove account {} and its key pair?", email)) {
+        console.warn("Aborted!");
+        return 1;
+    }
+
+    match remove_config(email) {
+        Ok(_) => {
+            console.info(cformat!("Removed account <m,s>{}</>", email));
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to remove account: <r,s>{}</>", e));
+            1
+        }
+    }
+}
+
+pub(crate) fn accounts_default(
+    email: Option<&str>,
+    clear: bool,
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    if clear {
+        return match set_default_account(None) {
+            Ok(_) => {
+                console.info("Cleared the default account");
+                0
+            }
+            Err(e) => {
+                console.error(cformat!("Failed to clear default account: <r,s>{}</>", e));
+                1
+            }
+        };
+    }
+
+    let email = match email {
+        Some(email) => email,
+        None => {
+            match get_default_account() {
+                Some(email) => console.info(cformat!("Default account: <m,s>{}</>", email)),
+                None => console.info("No default account set"),
             }
+            return 0;
+        }
+    };
+
+    if !account_exists(email, passphrase, console) {
+        return 1;
+    }
 
+    match set_default_account(Some(email)) {
+        Ok(_) => {
+            console.info(cformat!("Set <m,s>{}</> as the default account", email));
             0
         }
+        Err(e) => {
+            console.error(cformat!("Failed to set default account: <r,s>{}</>", e));
+            1
+        }
+    }
+}
+
+pub(crate) async fn accounts_relogin(
+    email: Option<&str>,
+    password: &PasswordArgs,
+    passphrase: Option<&Passphrase>,
+    proxy: Option<reqwest::Proxy>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    let old_config = match select_single_account(email, passphrase, console) {
+        Some(config) => config,
+        None => return 1,
+    };
+
+    let password = match resolve_password(password, console) {
+        Some(password) if !password.is_empty() => password,
+        _ => {
+            console.error("No password provided!");
+            return 1;
+        }
+    };
+
+    console.info(cformat!(
+        "Logging in again as <m,s>{}</>...",
+        old_config.email()
+    ));
+    match Client::login(old_config.email(), password, proxy).await {
+        Ok(account) => {
+            let mut config = Config::from(&account);
+
+            if old_config.has_key() && old_config.get_key_pair(passphrase).is_ok() {
+                // Keep the existing key pair
+                config.set_private_key(&old_config.private_key().to_string_lossy());
+                config.set_public_key(&old_config.public_key().to_string_lossy());
+            } else {
+                console.warn("Existing key pair is missing, generating a new one...");
+                if let Err(e) = config.generate_key_pair(passphrase) {
+                    console.error(cformat!("Failed to generate RSA key pair: <r,s>{}</>", e));
+                    return 1;
+                }
+            }
+
+            if let Err(e) = save_config(&config, passphrase) {
+                console.error(cformat!("Failed to save config: <r,s>{}</>", e));
+                return 1;
+            }
+
+            console.info(cformat!(
+                "Refreshed credentials for <m,s>{}</>",
+                config.email()
+            ));
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to login: <r,s>{}</>", e));
+            1
+        }
+    }
+}
+
+/// Unlock the encrypted accounts with the passphrase from the environment variable or a prompt
+///
+/// Without encrypted accounts, the passphrase from the environment variable is still used for the new ones.
+/// Returns `Err` if the passphrase is missing or wrong.
+pub(crate) fn unlock_accounts(console: &crate::term::Terminal) -> Result<Option<Passphrase>, ()> {
+    if !has_encrypted_config() {
+        return Ok(env_passphrase().map(Passphrase::new));
+    }
+
+    let passphrase = match env_passphrase() {
+        Some(passphrase) => passphrase,
+        None => match console.password("Passphrase:") {
+            Some(passphrase) => passphrase,
+            None => {
+                console.error("Your accounts are encrypted, a passphrase is required!");
+                return Err(());
+            }
+        },
+    };
+
+    match unlock(&passphrase) {
+        Some(passphrase) => Ok(Some(passphrase)),
+        None => {
+            console.error("Invalid passphrase!");
+            Err(())
+        }
+    }
+}
+
+pub(crate) fn accounts_encrypt(
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    let new_passphrase = match env_passphrase() {
+        Some(passphrase) => passphrase,
+        None => {
+            let passphrase = console.password("New passphrase:").unwrap_or_default();
+            let confirm = console.password("Confirm passphrase:").unwrap_or_default();
+            if passphrase != confirm {
+                console.error("Passphrase does not match!");
+                return 1;
+            }
+            passphrase
+        }
+    };
+
+    if new_passphrase.is_empty() {
+        console.error("No passphrase provided!");
+        return 1;
+    }
+
+    match change_passphrase(passphrase, Some(&Passphrase::new(new_passphrase))) {
+        Ok(count) => {
+            console.info(cformat!("Encrypted <s>{}</> accounts", count));
+            console.info(cformat!(
+                "You can use <m,s>{}</> to provide the passphrase non-interactively",
+                PASSPHRASE_ENV
+            ));
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to encrypt accounts: <r,s>{}</>", e));
+            1
+        }
+    }
+}
+
+pub(crate) fn accounts_decrypt(
+    passphrase: Option<&Passphrase>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    if !has_encrypted_config() {
+        console.warn("Your accounts are not encrypted!");
+        return 0;
+    }
+
+    match change_passphrase(passphrase, None) {
+        Ok(count) => {
+            console.info(cformat!("Decrypted <s>{}</> accounts", count));
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to decrypt accounts: <r,s>{}</>", e));
+            1
+        }
+    }
+}
+
+pub(crate) fn select_single_account(
+    email: Option<&str>,
+    passphrase: Option<&Passphrase>,
+    term: &crate::term::Terminal,
+) -> Option<Config> {
+    if let Some(email) = email {
+        match get_config(email, passphrase) {
+            Ok(Some(config)) => return Some(config),
+            Ok(None) => term.warn(format!("Account ID {} not found!", email)),
+            Err(e) => term.error(cformat!("Failed to read account: <r,s>{}</>", e)),
+        }
+
+        return None;
+    }
+
+    if let Some(email) = get_default_account() {
+        match get_config(&email, passphrase) {
+            Ok(Some(config)) => return Some(config),
+            Ok(None) => term.warn(format!("Default account {} not found!", email)),
+            Err(e) => term.warn(cformat!(
+                "Failed to read default account {}: <r,s>{}</>",
+                email,
+                e
+            )),
+        }
+    }
+
+    let all_configs = load_accounts(passphrase, term)?;
+    let all_choices: Vec<ConsoleChoice> = all_configs
+        .iter()
+        .map(|c| ConsoleChoice {
+            name: c.email().to_string(),
+            value: c.email().to_string(),
+        })
+        .collect();
+
+    if all_configs.is_empty() {
+        term.warn("No accounts found!");
+        return None;
+    }
+
+    // only 1? return
+    if all_configs.len() == 1 {
+        return Some(all_configs[0].clone());
+    }
+
+    let selected = term.choice("Select an account:", all_choices);
+    match selected {
+        Some(selected) => {
+            let config = all_configs
+                .iter()
+                .find(|c| c.email() == selected.name)
+                .unwrap()
+                .clone();
+
+            Some(config)
+        }
+        None => None,
     }
 }
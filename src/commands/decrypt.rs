@@ -0,0 +1,142 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use color_print::cformat;
+use emancipated::{config::Passphrase, decrypt::decrypt_page, kp, manifest::VolumeManifest};
+
+use crate::cli::{DownloadFormat, ExitCode};
+
+use super::{accounts::select_single_account, export::volume_export};
+
+/// Decrypt all the encrypted pages of the volume, the manifest is saved after each page
+///
+/// Returns `false` if any page failed to be decrypted.
+pub(crate) fn decrypt_pages(
+    console: &crate::term::Terminal,
+    directory: &std::path::Path,
+    manifest: &mut VolumeManifest,
+    private_key: &rsa::RsaPrivateKey,
+) -> bool {
+    let encrypted: Vec<usize> = manifest
+        .pages
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.encrypted_with.is_some())
+        .map(|(i, _)| i)
+        .collect();
+
+    console.info(cformat!(
+        "Decrypting <m,s>{}</> pages in <m,s>{}</>...",
+        encrypted.len(),
+        directory.display()
+    ));
+    let progress = console.make_progress(encrypted.len() as u64, Some("Decrypting"));
+
+    // The key is the same for all the pages downloaded together
+    let mut keys: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut failed = 0;
+    for position in encrypted {
+        let page = &mut manifest.pages[position];
+        let Some(hash) = page.encrypted_with.clone() else {
+            continue;
+        };
+
+        let aes_key = match keys.get(&hash) {
+            Some(aes_key) => aes_key,
+            None => match kp::hash_to_aes_key(private_key, &hash) {
+                Ok(aes_key) => keys.entry(hash).or_insert(aes_key),
+                Err(e) => {
+                    console.error(cformat!(
+                        "  Failed to make the key for <m,s>p{:03}</>, is this the right account? <r,s>{}</>",
+                        page.index,
+                        e
+                    ));
+                    failed += 1;
+                    progress.inc(1);
+                    continue;
+                }
+            },
+        };
+
+        match decrypt_page(directory, page, aes_key) {
+            Ok(_) => {
+                if let Err(e) = manifest.save(directory) {
+                    console.warn(cformat!("Failed to save manifest: <r,s>{}</>", e));
+                }
+            }
+            Err(e) => {
+                console.error(cformat!(
+                    "  Failed to decrypt <m,s>{}</>: <r,s>{}</>",
+                    page.filename,
+                    e
+                ));
+                failed += 1;
+            }
+        }
+        progress.inc(1);
+    }
+
+    progress.finish();
+
+    if failed > 0 {
+        console.error(cformat!(
+            "Failed to decrypt <r,s>{}</> pages, run the command again to retry.",
+            failed
+        ));
+    }
+
+    failed == 0
+}
+
+/// Decrypt a volume downloaded with `--keep-encrypted`, then package it if requested
+pub(crate) fn volume_decrypt(
+    console: &crate::term::Terminal,
+    directory: PathBuf,
+    format: Option<DownloadFormat>,
+    per_episode: bool,
+    account: Option<&str>,
+    passphrase: Option<&Passphrase>,
+) -> ExitCode {
+    let mut manifest = match VolumeManifest::load(&directory) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            console.error("No manifest found, is this a downloaded volume?");
+            return 1;
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to load manifest: <r,s>{}</>", e));
+            return 1;
+        }
+    };
+
+    if manifest.is_encrypted() {
+        // Prefer the account that downloaded the volume
+        let Some(config) =
+            select_single_account(account.or(manifest.account.as_deref()), passphrase, console)
+        else {
+            return 1;
+        };
+        let private_key = match config.get_key_pair(passphrase) {
+            Ok((private_key, _)) => private_key,
+            Err(e) => {
+                console.error(cformat!(
+                    "Failed to load the private key of <m,s>{}</>: <r,s>{}</>",
+                    config.email(),
+                    e
+                ));
+                return 1;
+            }
+        };
+
+        if !decrypt_pages(console, &directory, &mut manifest, &private_key) {
+            return 1;
+        }
+        console.info(cformat!("Decrypted <m,s>{}</>", directory.display()));
+    } else {
+        console.info("Volume is already decrypted!");
+    }
+
+    match format {
+        None | Some(DownloadFormat::Folder) => 0,
+        Some(format) => volume_export(console, directory, format, per_episode),
+    }
+}
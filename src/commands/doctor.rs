@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use color_print::cformat;
+
+use emancipated::{
+    config::{
+        get_config, get_default_account, get_user_path, quarantine_file, save_config, scan_configs,
+        set_default_account, upgrade_config_file, Config, ConfigError, Passphrase,
+    },
+    kp::RSAError,
+    settings::Settings,
+};
+
+use crate::cli::ExitCode;
+
+/// Keep track of the problems found and fixed
+#[derive(Default)]
+struct Report {
+    problems: usize,
+    fixed: usize,
+}
+
+impl Report {
+    fn problem(&mut self, msg: impl Into<String>, console: &crate::term::Terminal) {
+        self.problems += 1;
+        console.warn(msg);
+    }
+
+    fn fixed(&mut self, msg: impl Into<String>, console: &crate::term::Terminal) {
+        self.fixed += 1;
+        console.info(msg);
+    }
+}
+
+fn quarantine(path: &Path, report: &mut Report, console: &crate::term::Terminal) {
+    match quarantine_file(path) {
+        Ok(target) => report.fixed(cformat!("  Moved to <m,s>{}</>", target.display()), console),
+        Err(e) => console.error(cformat!("  Failed to quarantine: <r,s>{}</>", e)),
+    }
+}
+
+fn check_temporary_files(
+    user_path: &Path,
+    fix: bool,
+    report: &mut Report,
+    console: &crate::term::Terminal,
+) {
+    let Ok(entries) = std::fs::read_dir(user_path) else {
+        return;
+    };
+
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !(file_name.starts_with('.') && file_name.ends_with(".tmp")) {
+            continue;
+        }
+
+        report.problem(
+            cformat!("Leftover temporary file <m,s>{}</>", path.display()),
+            console,
+        );
+        if fix {
+            match std::fs::remove_file(&path) {
+                Ok(_) => report.fixed("  Removed", console),
+                Err(e) => console.error(cformat!("  Failed to remove: <r,s>{}</>", e)),
+            }
+        }
+    }
+}
+
+/// Move the key pair files of the account to the quarantine, returns `false` if any of them is left
+fn quarantine_keys(config: &Config, console: &crate::term::Terminal) -> bool {
+    if !config.has_key() {
+        return true;
+    }
+
+    for path in [config.private_key(), config.public_key()] {
+        if !path.exists() {
+            continue;
+        }
+        match quarantine_file(&path) {
+            Ok(target) => console.info(cformat!(
+                "  Moved the old key to <m,s>{}</>",
+                target.display()
+            )),
+            Err(e) => {
+                console.error(cformat!(
+                    "  Failed to quarantine the old key, not generating a new one: <r,s>{}</>",
+                    e
+                ));
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn check_accounts(
+    fix: bool,
+    passphrase: Option<&Passphrase>,
+    report: &mut Report,
+    console: &crate::term::Terminal,
+) {
+    let files = match scan_configs(passphrase) {
+        Ok(files) => files,
+        Err(e) => {
+            report.problem(cformat!("Unable to read accounts: <r,s>{}</>", e), console);
+            return;
+        }
+    };
+
+    for file in files {
+        let mut config = match file.config {
+            Ok(config) => config,
+            Err(ConfigError::Locked(path)) => {
+                report.problem(
+                    cformat!(
+                        "Account <m,s>{}</> is encrypted and cannot be checked without the passphrase",
+                        path.display()
+                    ),
+                    console,
+                );
+                continue;
+            }
+            Err(e) => {
+                report.problem(e.to_string(), console);
+                if fix && matches!(e, ConfigError::Corrupted(..)) {
+                    quarantine(&file.path, report, console);
+                }
+                continue;
+            }
+        };
+
+        if file.outdated {
+            report.problem(
+                cformat!(
+                    "Account <m,s>{}</> uses an older config version",
+                    config.email()
+                ),
+                console,
+            );
+            if fix {
+                match upgrade_config_file(&file.path, passphrase) {
+                    Ok(_) => report.fixed("  Upgraded to the latest version", console),
+                    Err(e) => console.error(cformat!("  Failed to upgrade: <r,s>{}</>", e)),
+                }
+            }
+        }
+
+        let key_error = if !config.has_key() {
+            Some("missing".to_string())
+        } else {
+            match config.get_key_pair(passphrase) {
+                Ok(_) => None,
+                Err(RSAError::MissingPassphrase) => {
+                    report.problem(
+                        cformat!(
+                            "Key pair for <m,s>{}</> is encrypted and cannot be checked without the passphrase",
+                            config.email()
+                        ),
+                        console,
+                    );
+                    continue;
+                }
+                Err(e) => Some(e.to_string()),
+            }
+        };
+
+        if let Some(reason) = key_error {
+            report.problem(
+                cformat!(
+                    "Key pair for <m,s>{}</> is unusable: {}",
+                    config.email(),
+                    reason
+                ),
+                console,
+            );
+            if fix {
+                // Keep the old key, the pages kept encrypted might still need it
+                if !quarantine_keys(&config, console) {
+                    continue;
+                }
+                match config
+                    .generate_key_pair(passphrase)
+                    .and_then(|_| save_config(&config, passphrase))
+                {
+                    Ok(_) => report.fixed("  Generated a new key pair", console),
+                    Err(e) => console.error(cformat!(
+                        "  Failed to generate a new key pair: <r,s>{}</>",
+                        e
+                    )),
+                }
+            }
+        }
+    }
+
+    if let Some(email) = get_default_account() {
+        if let Ok(None) = get_config(&email, passphrase) {
+            report.problem(
+                cformat!("Default account <m,s>{}</> does not exist", email),
+                console,
+            );
+            if fix {
+                match set_default_account(None) {
+                    Ok(_) => report.fixed("  Cleared the default account", console),
+                    Err(e) => console.error(cformat!("  Failed to clear: <r,s>{}</>", e)),
+                }
+            }
+        }
+    }
+}
+
+fn check_settings(fix: bool, report: &mut Report, console: &crate::term::Terminal) {
+    if let Err(e) = Settings::load() {
+        report.problem(cformat!("Invalid settings: <r,s>{}</>", e), console);
+        if fix {
+            if let Ok(path) = Settings::path() {
+                quarantine(&path, report, console);
+            }
+        }
+    }
+}
+
+/// Check the user path for broken accounts, keys, and settings
+pub(crate) fn doctor(fix: bool, console: &crate::term::Terminal) -> ExitCode {
+    let user_path = match get_user_path() {
+        Ok(user_path) => user_path,
+        Err(e) => {
+            console.error(cformat!("Unable to find the user path: <r,s>{}</>", e));
+            return 1;
+        }
+    };
+    console.info(cformat!("Checking <m,s>{}</>...", user_path.display()));
+
+    // A wrong passphrase is reported per account below
+    let passphrase = super::accounts::unlock_accounts(console).unwrap_or_default();
+
+    let mut report = Report::default();
+    check_temporary_files(&user_path, fix, &mut report, console);
+    check_accounts(fix, passphrase.as_ref(), &mut report, console);
+    check_settings(fix, &mut report, console);
+
+    if report.problems == 0 {
+        console.info("No problems found!");
+        return 0;
+    }
+
+    if !fix {
+        console.warn(cformat!(
+            "Found <s>{}</> problems, run with <m,s>--fix</> to repair them",
+            report.problems
+        ));
+        return 1;
+    }
+
+    console.info(cformat!(
+        "Fixed <s>{}</> of <s>{}</> problems",
+        report.fixed,
+        report.problems
+    ));
+    if report.fixed < report.problems {
+        1
+    } else {
+        0
+    }
+}
@@ -1,35 +1,277 @@
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use color_print::cformat;
+use futures::StreamExt;
 
-use crate::{
-    cli::ExitCode,
-    client::{Client, ClientError},
-    config::save_config,
-    image::load_and_save_image,
+use emancipated::{
+    decrypt::ENCRYPTED_EXTENSION,
+    device::{process_image, DeviceOptions},
+    export::comicinfo::ComicInfo,
+    image::{decode_image, decrypt_image, image_dimensions, ImageError},
     kp,
+    manifest::{
+        hash_bytes, ManifestFile, ManifestPage, ManifestSpread, ManifestStatus, VolumeManifest,
+    },
+    models::{Comic, Image, Volume},
+    retry::RetryPolicy,
+    spread::{merge_spreads, SpreadMode},
+    template::{Template, TemplateValues},
+    transcode::{transcode, TranscodeOptions},
+    Client, ClientError,
 };
 
+use crate::cli::{
+    CoverMode, DownloadFormat, DownloadLayout, ExitCode, SpreadMerge, VolumeRange, VolumeSelector,
+};
+
+use super::{accounts::save_client_config, decrypt::decrypt_pages, export::export_volume};
+
+/// The options for the download command
+pub(crate) struct DownloadOptions {
+    /// The root directory for the downloads
+    pub(crate) output: PathBuf,
+    /// How the pages are laid out inside the volume directory
+    pub(crate) layout: DownloadLayout,
+    /// Whether to download the volume cover
+    pub(crate) cover: CoverMode,
+    /// The template for the volume directory inside the output
+    pub(crate) dir_template: Template,
+    /// The template for the page filename inside the volume directory
+    pub(crate) file_template: Template,
+    /// How many pages to download concurrently, `1` means sequential
+    pub(crate) jobs: usize,
+    /// The output format
+    pub(crate) format: DownloadFormat,
+    /// Package each episode separately
+    pub(crate) per_episode: bool,
+    /// Re-encode the pages after decrypting them
+    pub(crate) transcode: Option<TranscodeOptions>,
+    /// Prepare the pages for an e-reader, a split spread is saved as two files
+    pub(crate) device: Option<DeviceOptions>,
+    /// Merge the double-page spreads after the volume is downloaded
+    pub(crate) spreads: Option<SpreadMerge>,
+    /// Save the pages without decrypting them
+    pub(crate) keep_encrypted: bool,
+    /// The retry policy for each page
+    pub(crate) retry: RetryPolicy,
+}
+
+/// The reason a volume is skipped from the download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SkipReason {
+    Missing,
+    Unpurchased,
+    Unreleased,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Missing => write!(f, "not found"),
+            SkipReason::Unpurchased => write!(f, "not purchased"),
+            SkipReason::Unreleased => write!(f, "not released yet"),
+        }
+    }
+}
+
+fn is_volume_released(volume: &Volume) -> bool {
+    match &volume.release_at {
+        Some(release_at) => {
+            match time::OffsetDateTime::parse(
+                release_at,
+                &time::format_description::well_known::Rfc3339,
+            ) {
+                Ok(release_at) => release_at <= time::OffsetDateTime::now_utc(),
+                // Unknown format, assume it's already released
+                Err(_) => true,
+            }
+        }
+        None => true,
+    }
+}
+
+fn check_volume(volume: &Volume) -> Result<(), SkipReason> {
+    if !is_volume_released(volume) {
+        Err(SkipReason::Unreleased)
+    } else if !volume.purchased {
+        Err(SkipReason::Unpurchased)
+    } else {
+        Ok(())
+    }
+}
+
+/// Resolve the volume selector into the actual volumes to download
+///
+/// Returns the volumes that can be downloaded and the volume number that are skipped.
+fn resolve_volumes(
+    volumes: &[Volume],
+    selector: &VolumeSelector,
+) -> (Vec<Volume>, Vec<(i32, SkipReason)>) {
+    let mut selected: Vec<&Volume> = vec![];
+    let mut skipped: Vec<(i32, SkipReason)> = vec![];
+
+    match selector {
+        VolumeSelector::All => {
+            for volume in volumes.iter().filter(|v| v.purchased) {
+                match check_volume(volume) {
+                    Ok(_) => selected.push(volume),
+                    Err(reason) => skipped.push((volume.number, reason)),
+                }
+            }
+        }
+        VolumeSelector::Latest => {
+            let latest = volumes
+                .iter()
+                .filter(|v| is_volume_released(v))
+                .max_by_key(|v| v.number);
+
+            if let Some(latest) = latest {
+                match check_volume(latest) {
+                    Ok(_) => selected.push(latest),
+                    Err(reason) => skipped.push((latest.number, reason)),
+                }
+            }
+        }
+        VolumeSelector::Ranges(ranges) => {
+            for range in ranges {
+                if let VolumeRange::Single(number) = range {
+                    if !volumes.iter().any(|v| v.number == *number) {
+                        skipped.push((*number, SkipReason::Missing));
+                        continue;
+                    }
+                }
+
+                for volume in volumes.iter().filter(|v| range.contains(v.number)) {
+                    if selected.iter().any(|v| v.number == volume.number)
+                        || skipped.iter().any(|(n, _)| *n == volume.number)
+                    {
+                        continue;
+                    }
+
+                    match check_volume(volume) {
+                        Ok(_) => selected.push(volume),
+                        Err(reason) => skipped.push((volume.number, reason)),
+                    }
+                }
+            }
+        }
+    }
+
+    let mut selected: Vec<Volume> = selected.into_iter().cloned().collect();
+    selected.sort_by_key(|v| v.number);
+    skipped.sort_by_key(|(n, _)| *n);
+
+    (selected, skipped)
+}
+
 pub(crate) async fn manga_download(
     client: &mut Client,
     console: &crate::term::Terminal,
     slug: impl Into<String>,
-    volume: u32,
-    parallel: bool,
+    volume: VolumeSelector,
+    options: DownloadOptions,
 ) -> ExitCode {
     let slug: String = slug.into();
-    console.info(cformat!("Downloading manga <m,s>{}</>...", &slug));
+    console.info(cformat!("Fetching volumes for <m,s>{}</>...", &slug));
+
+    let comic = match client.get_volumes(&slug).await {
+        Ok(comic) => comic,
+        Err(e) => {
+            console.error(cformat!("Failed to fetch volumes: <r,s>{}</>", e));
+            return 1;
+        }
+    };
+
+    save_client_config(client, console);
+
+    let (volumes, skipped) = resolve_volumes(&comic.volumes, &volume);
+    for (number, reason) in skipped.iter() {
+        console.warn(cformat!(
+            "Skipping volume <m,s>{}</>: <y,s>{}</>",
+            number,
+            reason
+        ));
+    }
+
+    if volumes.is_empty() {
+        console.warn("No volumes to download!");
+        return 1;
+    }
+
+    console.info(cformat!(
+        "Downloading <m,s>{}</> volumes for <m,s>{}</>...",
+        volumes.len(),
+        &comic.comic.title
+    ));
 
-    match client.get_contents(&slug, volume.try_into().unwrap()).await {
+    // Only known when the title is completed, used for the metadata
+    let volume_count = match comic.comic.metadata.completed {
+        Some(true) => Some(comic.volumes.len()),
+        _ => None,
+    };
+
+    let mut failed_volumes = vec![];
+    for volume in volumes.iter() {
+        let info = ComicInfo::new(&comic.comic, volume).with_count(volume_count);
+        let exit_code = download_volume(
+            client,
+            console,
+            &slug,
+            volume.number,
+            &comic.comic,
+            info,
+            &options,
+        )
+        .await;
+        if exit_code != 0 {
+            failed_volumes.push(volume.number);
+        }
+    }
+
+    if !failed_volumes.is_empty() {
+        let failed_text = failed_volumes
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        console.error(cformat!(
+            "Failed to download volume(s): <r,s>{}</>",
+            failed_text
+        ));
+        return 1;
+    }
+
+    0
+}
+
+async fn download_volume(
+    client: &mut Client,
+    console: &crate::term::Terminal,
+    slug: &str,
+    volume: i32,
+    comic: &Comic,
+    info: ComicInfo,
+    options: &DownloadOptions,
+) -> ExitCode {
+    console.info(cformat!(
+        "Downloading volume <m,s>{}</> of <m,s>{}</>...",
+        volume,
+        slug
+    ));
+
+    match client.get_contents(slug, volume).await {
         Err(e) => {
             console.error(cformat!("Failed to initiate download: <r,s>{}</>", e));
             1
         }
         Ok(contents_data) => {
-            save_config(client.get_config());
+            save_client_config(client, console);
 
             let contents = &contents_data.contents;
 
@@ -43,116 +285,270 @@ pub(crate) async fn manga_download(
                 contents.episodes.len(),
                 volume
             ));
-            let ep_dir = get_output_directory(&slug, volume, true);
+            let values = TemplateValues::new(comic, &contents_data.volume);
+            let ep_dir = get_output_directory(&options.output, &options.dir_template, &values);
+            let naming = PageNaming {
+                file_template: options.file_template.clone(),
+                values,
+                layout: options.layout,
+            };
+            if let Err(e) = std::fs::create_dir_all(&ep_dir) {
+                console.error(cformat!(
+                    "Failed to create <m,s>{}</>: <r,s>{}</>",
+                    ep_dir.display(),
+                    e
+                ));
+                return 1;
+            }
+
+            let include_cover = match options.cover {
+                CoverMode::Auto => !contents_data.volume.reader_skip_cover,
+                CoverMode::Keep => true,
+                CoverMode::Skip => false,
+            };
+            let total_pages = get_slice_image_count(&contents.episodes) + include_cover as usize;
+            let fingerprint = options_fingerprint(options);
+            let mut manifest = match VolumeManifest::load(&ep_dir) {
+                Ok(Some(manifest)) => manifest,
+                Ok(None) => VolumeManifest::new(slug, volume, total_pages),
+                Err(e) => {
+                    console.warn(cformat!(
+                        "Failed to load manifest, starting fresh: <r,s>{}</>",
+                        e
+                    ));
+                    VolumeManifest::new(slug, volume, total_pages)
+                }
+            };
+
+            let status = manifest.status(&ep_dir, slug, volume, total_pages, &fingerprint);
+            match status {
+                ManifestStatus::OtherVolume => {
+                    console.error(cformat!(
+                        "<m,s>{}</> already has volume <m,s>{}</> of <m,s>{}</>, use a directory template with <m,s>{{volume}}</>",
+                        ep_dir.display(),
+                        manifest.volume,
+                        manifest.slug
+                    ));
+                    return 1;
+                }
+                ManifestStatus::Outdated => {
+                    console.warn(
+                        "The volume was downloaded with different options, downloading it again...",
+                    );
+                    manifest = VolumeManifest::new(slug, volume, total_pages);
+                }
+                ManifestStatus::Complete | ManifestStatus::Partial => {}
+            }
+
+            if status == ManifestStatus::Complete {
+                console.info(cformat!(
+                    "Volume <m,s>{}</> is already downloaded, skipping...",
+                    volume
+                ));
+                if let Some(exit_code) =
+                    handle_encrypted_pages(console, client, &ep_dir, &mut manifest, options)
+                {
+                    return exit_code;
+                }
+                // Downloaded before with another `--spreads`
+                if options.spreads.map(SpreadMode::from) != manifest.spread_mode {
+                    update_spreads(
+                        console,
+                        &ep_dir,
+                        &mut manifest,
+                        &contents.episodes,
+                        options,
+                        false,
+                    )
+                    .await;
+                }
+                return export_volume(
+                    console,
+                    &ep_dir,
+                    &manifest,
+                    &info,
+                    options.format,
+                    options.per_episode,
+                );
+            }
+
+            manifest.total_pages = total_pages;
+            manifest.options = fingerprint;
+            manifest.complete = false;
+            manifest.comic = Some(comic.clone());
+            manifest.volume_info = Some(contents_data.volume.clone());
+            manifest.account = Some(client.get_config().email().to_string());
+            if !include_cover {
+                // Downloaded before with the cover
+                manifest.remove_page(0);
+            }
 
-            // Download cover
             console.info(cformat!("Downloading to <m,s>{}</>...", ep_dir.display()));
-            let cover_bytes = client
-                .download_image(&contents_data.volume.cover.url)
-                .await
-                .unwrap();
 
-            let cover_path = ep_dir.join("p000.jpg");
-            tokio::fs::write(&cover_path, &cover_bytes).await.unwrap();
+            let manifest = SharedManifest::new(manifest);
 
             match kp::hash_to_aes_key(client.get_private_key(), &contents.hash) {
                 Ok(aes_key) => {
                     // Download all the images
                     console.log(format!("AES Key generated successfully: {:?}", &aes_key));
                     console.log(format!("Original AES hash: {}", &contents.hash));
-                    for (episode_idx, episode) in contents.episodes.iter().enumerate() {
-                        console.info(cformat!(
-                            "  Downloading episode <m,s>{}</> for <m,s>{}</>...",
-                            episode.episode,
-                            &slug,
-                        ));
+                    let encrypted_with = options.keep_encrypted.then(|| contents.hash.clone());
 
+                    // Flatten all the pages so the whole volume goes through the same worker pool
+                    // The index is always across the whole volume, only the page number follows the layout
+                    let mut all_pages: Vec<(usize, usize, Option<i32>, &Image)> = vec![];
+                    if include_cover {
+                        all_pages.push((0, 0, None, &contents_data.volume.cover));
+                    }
+                    for (episode_idx, episode) in contents.episodes.iter().enumerate() {
                         let prev_slice_count = if episode_idx == 0 {
                             0usize
                         } else {
                             get_slice_image_count(&contents.episodes[..episode_idx])
                         };
 
-                        let progress = Arc::new(
-                            console.make_progress(episode.pages.len() as u64, Some("Downloading")),
-                        );
-
-                        if parallel {
-                            let tasks: Vec<_> = episode
-                                .pages
-                                .iter()
-                                .enumerate()
-                                .map(|(idx, image)| {
-                                    let wrap_client = client.clone();
-                                    let image_dir = ep_dir.clone();
-                                    let cnsl = console.clone();
-                                    let image = image.clone();
-                                    let key = aes_key.clone();
-                                    let progress = Arc::clone(&progress);
-
-                                    let actual_page_idx = prev_slice_count + idx;
-
-                                    tokio::spawn(async move {
-                                        match actual_downloader(
-                                            DownloadNode {
-                                                client: wrap_client,
-                                                url: image.url.clone(),
-                                                idx: actual_page_idx + 1,
-                                                key,
-                                            },
-                                            cnsl.clone(),
-                                            &image_dir,
-                                            progress,
-                                        )
-                                        .await
-                                        {
-                                            Ok(_) => {}
-                                            Err(e) => {
-                                                cnsl.error(format!(
-                                                    "    Failed to download <m,s>{}</>: <r,s>{}</>",
-                                                    image.url, e
-                                                ));
-                                            }
-                                        }
-                                    })
-                                })
-                                .collect();
+                        for (idx, image) in episode.pages.iter().enumerate() {
+                            let page_idx = prev_slice_count + idx + 1;
+                            let page = match options.layout {
+                                DownloadLayout::Flat => page_idx,
+                                DownloadLayout::Episode => idx + 1,
+                            };
+                            all_pages.push((page_idx, page, Some(episode.episode), image));
+                        }
+                    }
 
-                            futures::future::join_all(tasks).await;
-                        } else {
-                            for (idx, image) in episode.pages.iter().enumerate() {
-                                let actual_page_idx = prev_slice_count + idx;
+                    let progress = Arc::new(
+                        console.make_progress(all_pages.len() as u64, Some("Downloading")),
+                    );
+
+                    // Skip the pages that are already downloaded
+                    let pending_pages: Vec<(usize, usize, Option<i32>, &Image)> = {
+                        let manifest = manifest.manifest.lock().unwrap();
+                        all_pages
+                            .into_iter()
+                            .filter(|(page_idx, _, _, _)| {
+                                let is_complete = manifest.is_page_complete(&ep_dir, *page_idx);
+                                if is_complete {
+                                    progress.inc(1);
+                                }
+                                !is_complete
+                            })
+                            .collect()
+                    };
+
+                    console.info(cformat!(
+                        "  Downloading <m,s>{}</> pages with <m,s>{}</> worker(s)...",
+                        pending_pages.len(),
+                        options.jobs
+                    ));
 
+                    // Only `jobs` pages are in flight at once, the next one starts as soon as one finishes
+                    let mut failed_pages: Vec<FailedPage> =
+                        futures::stream::iter(pending_pages.iter())
+                            .map(|(page_idx, page, episode, image)| {
                                 let node = DownloadNode {
                                     client: client.clone(),
                                     url: image.url.clone(),
-                                    idx: actual_page_idx + 1,
+                                    idx: *page_idx,
+                                    page: *page,
+                                    episode: *episode,
                                     key: aes_key.clone(),
+                                    retry: options.retry,
+                                    naming: naming.clone(),
+                                    transcode: options.transcode,
+                                    device: options.device,
+                                    encrypted_with: encrypted_with.clone(),
                                 };
+                                let progress = Arc::clone(&progress);
+                                let manifest = Arc::clone(&manifest);
+                                let ep_dir = &ep_dir;
 
-                                match actual_downloader(
-                                    node,
-                                    console.clone(),
-                                    &ep_dir,
-                                    progress.clone(),
-                                )
-                                .await
-                                {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        console.error(format!(
-                                            "    Failed to download <m,s>{}</>: <r,s>{}</>",
-                                            image.url, e
-                                        ));
-                                    }
+                                async move {
+                                    actual_downloader(
+                                        node,
+                                        console.clone(),
+                                        ep_dir,
+                                        progress,
+                                        manifest,
+                                    )
+                                    .await
+                                    .err()
+                                    .map(|e| FailedPage {
+                                        idx: *page_idx,
+                                        episode: *episode,
+                                        url: image.url.clone(),
+                                        error: e.to_string(),
+                                    })
                                 }
-                            }
+                            })
+                            .buffer_unordered(options.jobs)
+                            .filter_map(futures::future::ready)
+                            .collect()
+                            .await;
+
+                    progress.finish();
+
+                    // Every page is done, so the lock is not held across the spreads detection
+                    let mut manifest = manifest.manifest.lock().unwrap().clone();
+                    manifest.complete = manifest.pages.len() == manifest.total_pages;
+                    if let Err(e) = manifest.save(&ep_dir) {
+                        console.warn(cformat!("Failed to save manifest: <r,s>{}</>", e));
+                    }
+
+                    if !failed_pages.is_empty() {
+                        failed_pages.sort_by_key(|p| p.idx);
+                        console.error(cformat!(
+                            "Failed to download <r,s>{}</> pages in volume <m,s>{}</>:",
+                            failed_pages.len(),
+                            volume
+                        ));
+                        for page in failed_pages.iter() {
+                            let location = match page.episode {
+                                Some(episode) => format!("episode {}", episode),
+                                None => "cover".to_string(),
+                            };
+                            console.error(cformat!(
+                                "  - <s>p{:03}</> ({}): <r,s>{}</>",
+                                page.idx,
+                                location,
+                                page.error
+                            ));
+                            console.log(format!("    URL: {}", page.url));
                         }
+                    }
 
-                        progress.finish();
+                    if !manifest.complete {
+                        console.error(cformat!(
+                            "Volume <m,s>{}</> is incomplete, run the command again to resume.",
+                            volume
+                        ));
+                        return 1;
                     }
 
-                    0
+                    if let Some(exit_code) =
+                        handle_encrypted_pages(console, client, &ep_dir, &mut manifest, options)
+                    {
+                        return exit_code;
+                    }
+
+                    update_spreads(
+                        console,
+                        &ep_dir,
+                        &mut manifest,
+                        &contents.episodes,
+                        options,
+                        true,
+                    )
+                    .await;
+
+                    export_volume(
+                        console,
+                        &ep_dir,
+                        &manifest,
+                        &info,
+                        options.format,
+                        options.per_episode,
+                    )
                 }
                 Err(e) => {
                     console.error(cformat!(
@@ -167,25 +563,277 @@ pub(crate) async fn manga_download(
     }
 }
 
-fn get_output_directory(slug: &str, volume: u32, create_folder: bool) -> PathBuf {
-    let cwd = std::env::current_dir().unwrap();
-    let mut pathing = cwd.join("DOWNLOADS");
-    pathing.push(slug);
+/// Decrypt the pages kept encrypted by an earlier download, unless they should stay encrypted
+///
+/// Returns the exit code if the volume should not be packaged.
+fn handle_encrypted_pages(
+    console: &crate::term::Terminal,
+    client: &Client,
+    ep_dir: &Path,
+    manifest: &mut VolumeManifest,
+    options: &DownloadOptions,
+) -> Option<ExitCode> {
+    if !manifest.is_encrypted() {
+        return None;
+    }
+
+    if options.keep_encrypted {
+        console.info(cformat!(
+            "The pages are kept encrypted, run <m,s>emancipated decrypt \"{}\"</> to decrypt them.",
+            ep_dir.display()
+        ));
+        return Some(0);
+    }
+
+    if decrypt_pages(console, ep_dir, manifest, client.get_private_key()) {
+        None
+    } else {
+        Some(1)
+    }
+}
+
+/// A fingerprint of the options that change the saved pages, see [`VolumeManifest::options`]
+fn options_fingerprint(options: &DownloadOptions) -> String {
+    let options = format!(
+        "{:?}|{:?}|{:?}|{:?}",
+        options.layout, options.file_template, options.transcode, options.device
+    );
+
+    hash_bytes(options.as_bytes())
+}
+
+/// Merge the double-page spreads of the downloaded volume and record them in the manifest
+///
+/// The spreads found before are reused when only the mode changes, unless `redetect` is set
+/// because the pages were just downloaded. Without `--spreads`, the merged spreads are removed.
+async fn update_spreads(
+    console: &crate::term::Terminal,
+    ep_dir: &Path,
+    manifest: &mut VolumeManifest,
+    episodes: &[emancipated::models::ContentEpisodes],
+    options: &DownloadOptions,
+    redetect: bool,
+) {
+    let mode = options.spreads.map(SpreadMode::from);
+    match mode {
+        None if manifest.spread_mode.is_none() && manifest.spreads.is_empty() => return,
+        None => {
+            for spread in manifest.spreads.drain(..) {
+                // Not fatal, the file is not used anymore
+                let _ = std::fs::remove_file(ep_dir.join(spread.filename));
+            }
+            manifest.spread_mode = None;
+        }
+        Some(mode) if manifest.spread_mode.is_some() && !redetect => {
+            for spread in manifest.spreads.iter_mut() {
+                spread.replace = mode == SpreadMode::Replace;
+            }
+            manifest.spread_mode = Some(mode);
+        }
+        Some(mode) => match detect_spreads(ep_dir, manifest, episodes, mode, options).await {
+            Ok(spreads) => {
+                console.info(cformat!(
+                    "  Found <m,s>{}</> double-page spread(s)",
+                    spreads.len()
+                ));
+                manifest.spreads = spreads;
+                manifest.spread_mode = Some(mode);
+            }
+            Err(e) => {
+                console.warn(cformat!(
+                    "Failed to merge the spreads, skipping: <r,s>{}</>",
+                    e
+                ));
+                return;
+            }
+        },
+    }
+
+    if let Err(e) = manifest.save(ep_dir) {
+        console.warn(cformat!("Failed to save manifest: <r,s>{}</>", e));
+    }
+}
+
+/// Find and merge the spreads on a blocking thread, since every candidate page is decoded
+async fn detect_spreads(
+    ep_dir: &Path,
+    manifest: &VolumeManifest,
+    episodes: &[emancipated::models::ContentEpisodes],
+    mode: SpreadMode,
+    options: &DownloadOptions,
+) -> Result<Vec<ManifestSpread>, ImageError> {
+    // Consecutive pages in the same episode with the same height might be a spread
+    let mut candidates = vec![];
+    for (episode_idx, episode) in episodes.iter().enumerate() {
+        let prev_slice_count = get_slice_image_count(&episodes[..episode_idx]);
+        for (idx, pair) in episode.pages.windows(2).enumerate() {
+            if pair[0].height == pair[1].height {
+                candidates.push(prev_slice_count + idx + 1);
+            }
+        }
+    }
+
+    let transcode = options.transcode.unwrap_or_default();
+    let ep_dir = ep_dir.to_path_buf();
+    let manifest = manifest.clone();
+    tokio::task::spawn_blocking(move || {
+        merge_spreads(&ep_dir, &manifest, &candidates, mode, &transcode)
+    })
+    .await
+    .map_err(|e| ImageError::Io(std::io::Error::other(e)))?
+}
+
+fn get_output_directory(root: &Path, template: &Template, values: &TemplateValues) -> PathBuf {
+    let root = match std::env::current_dir() {
+        Ok(cwd) => cwd.join(root),
+        Err(_) => root.to_path_buf(),
+    };
+
+    root.join(template.render(values))
+}
+
+/// Build the page filenames from the file template and the layout
+#[derive(Clone)]
+struct PageNaming {
+    file_template: Template,
+    values: TemplateValues,
+    layout: DownloadLayout,
+}
+
+impl PageNaming {
+    /// Get the page filename relative to the volume directory
+    ///
+    /// The extra files of a page (`part` above 0) are suffixed with `_N`, e.g. `p005_1.png`.
+    fn filename(&self, page: usize, episode: Option<i32>, ext: &str, part: usize) -> PathBuf {
+        let mut filename = self
+            .file_template
+            .render(&self.values.clone().with_page(page, episode, ext));
+
+        if part > 0 {
+            let name = match (filename.file_stem(), filename.extension()) {
+                (Some(stem), Some(ext)) => format!(
+                    "{}_{}.{}",
+                    stem.to_string_lossy(),
+                    part,
+                    ext.to_string_lossy()
+                ),
+                _ => format!("{}_{}", filename.to_string_lossy(), part),
+            };
+            filename.set_file_name(name);
+        }
+
+        match (self.layout, episode) {
+            (DownloadLayout::Episode, Some(episode)) => {
+                PathBuf::from(format!("e{:03}", episode)).join(filename)
+            }
+            _ => filename,
+        }
+    }
+}
+
+/// The filename stored in the manifest, always separated with `/` so it works in the archives
+fn manifest_filename(filename: &Path) -> String {
+    filename
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Write the page, creating the parent directories from the file template
+fn write_page(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
 
-    pathing.push(format!("v{:02}", volume));
+    std::fs::write(path, data)
+}
 
-    if create_folder {
-        std::fs::create_dir_all(&pathing).unwrap();
+/// The manifest shared by all the download tasks
+struct SharedManifest {
+    manifest: Mutex<VolumeManifest>,
+    /// Bumped on each change while holding the manifest lock
+    revision: AtomicU64,
+    /// The last saved revision, held while writing so the saves never overlap
+    saved: Mutex<u64>,
+}
+
+impl SharedManifest {
+    fn new(manifest: VolumeManifest) -> Arc<Self> {
+        Arc::new(Self {
+            manifest: Mutex::new(manifest),
+            revision: AtomicU64::new(0),
+            saved: Mutex::new(0),
+        })
     }
 
-    pathing
+    /// Record the page and save the manifest, so the download can resume when interrupted
+    ///
+    /// Only the serialization happens under the lock, the file is written on a blocking thread.
+    async fn add_page(
+        self: &Arc<Self>,
+        directory: &Path,
+        page: ManifestPage,
+    ) -> Result<(), std::io::Error> {
+        let (revision, data) = {
+            let mut manifest = self.manifest.lock().unwrap();
+            manifest.add_page(page);
+            let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+            (revision, manifest.to_bytes()?)
+        };
+
+        let shared = Arc::clone(self);
+        let directory = directory.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut saved = shared.saved.lock().unwrap();
+            // A newer manifest is already saved
+            if *saved >= revision {
+                return Ok(());
+            }
+
+            VolumeManifest::save_bytes(&directory, &data)?;
+            *saved = revision;
+            Ok(())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
 }
 
 struct DownloadNode {
     client: Client,
     url: String,
     idx: usize,
+    /// `None` for the cover
+    episode: Option<i32>,
     key: Vec<u8>,
+    retry: RetryPolicy,
+    /// The page number used in the filename
+    page: usize,
+    naming: PageNaming,
+    transcode: Option<TranscodeOptions>,
+    device: Option<DeviceOptions>,
+    /// The volume hash when the page is kept encrypted
+    encrypted_with: Option<String>,
+}
+
+/// A page that failed to be downloaded even after retrying
+struct FailedPage {
+    idx: usize,
+    episode: Option<i32>,
+    url: String,
+    error: String,
+}
+
+/// Check if the error can be retried
+///
+/// Decryption or decoding failure is also retried since it might be caused by a truncated response.
+fn is_retryable(error: &ClientError) -> bool {
+    match error {
+        ClientError::Image(ImageError::Io(_)) => false,
+        ClientError::Image(_) => true,
+        _ => error.is_transient(),
+    }
 }
 
 async fn actual_downloader(
@@ -193,6 +841,7 @@ async fn actual_downloader(
     console: crate::term::Terminal,
     path: &Path,
     progress: Arc<indicatif::ProgressBar>,
+    manifest: Arc<SharedManifest>,
 ) -> Result<(), ClientError> {
     if console.is_debug() {
         console.log(cformat!(
@@ -202,37 +851,206 @@ async fn actual_downloader(
         ));
     }
 
-    let image_fn = format!("p{:03}.bin", node.idx);
-    let img_dl_path = path.join(&image_fn);
+    let result = node
+        .retry
+        .run(
+            || fetch_and_save(&node, path, &manifest),
+            is_retryable,
+            |attempt, delay, err| {
+                console.warn(cformat!(
+                    "    Failed to download <m,s>p{:03}</>, retrying in {:.1}s ({}/{}): <y,s>{}</>",
+                    node.idx,
+                    delay.as_secs_f32(),
+                    attempt,
+                    node.retry.max_retries,
+                    err
+                ));
+            },
+        )
+        .await;
 
-    match node.client.download_image(&node.url).await {
-        Ok(dyn_image) => {
-            // Decrypt the image and save
-            match load_and_save_image(&dyn_image, &node.key, &img_dl_path) {
-                Ok(_) => {
-                    progress.inc(1);
-                    Ok(())
-                }
-                Err(err) => {
-                    console.error(format!(
-                        "    Failed to save <m,s>{}</>: <r,s>{}</>",
-                        &node.url, err
-                    ));
-                    Err(ClientError::Image(err))
-                }
-            }
+    match result {
+        Ok(_) => {
+            progress.inc(1);
+            Ok(())
         }
         Err(err) => {
-            console.error(format!(
+            console.error(cformat!(
                 "    Failed to download <m,s>{}</>: <r,s>{}</>",
-                &node.url, err
+                &node.url,
+                err
             ));
-
             Err(err)
         }
     }
 }
 
-fn get_slice_image_count(prev_episodes: &[crate::models::ContentEpisodes]) -> usize {
+/// Download, decrypt, optionally process and save a single page, then record it in the manifest
+async fn fetch_and_save(
+    node: &DownloadNode,
+    path: &Path,
+    manifest: &Arc<SharedManifest>,
+) -> Result<(), ClientError> {
+    let dyn_image = node.client.download_image(&node.url).await?;
+    if let Some(hash) = &node.encrypted_with {
+        let filename = node
+            .naming
+            .filename(node.page, node.episode, ENCRYPTED_EXTENSION, 0);
+        write_page(&path.join(&filename), &dyn_image).map_err(ImageError::Io)?;
+
+        let page = ManifestPage {
+            index: node.idx,
+            episode: node.episode,
+            url: node.url.clone(),
+            filename: manifest_filename(&filename),
+            hash: hash_bytes(&dyn_image),
+            extra_files: vec![],
+            encrypted_with: Some(hash.clone()),
+            dimensions: None,
+        };
+        manifest
+            .add_page(path, page)
+            .await
+            .map_err(ImageError::Io)?;
+
+        return Ok(());
+    }
+
+    // Decrypt the image and save, the cover might not be encrypted
+    // The decoding and encoding is CPU heavy, so keep it off the async workers
+    let (episode, key, device, options) =
+        (node.episode, node.key.clone(), node.device, node.transcode);
+    let images = tokio::task::spawn_blocking(move || {
+        let (decrypted, ext) = match episode {
+            Some(_) => decrypt_image(&dyn_image, &key)?,
+            None => decode_image(&dyn_image, &key)?,
+        };
+        let images = match (device, options) {
+            (Some(device), options) => {
+                process_image(&decrypted, &device, &options.unwrap_or_default())?
+            }
+            (None, Some(options)) => vec![transcode(&decrypted, &options)?],
+            (None, None) => vec![(decrypted, ext)],
+        };
+        // Recorded for the spread detection, so the pages do not need to be decoded again
+        let dimensions = images
+            .first()
+            .and_then(|(data, _)| image_dimensions(data).ok());
+        Ok::<_, ImageError>((images, dimensions))
+    })
+    .await
+    .map_err(|e| ImageError::Io(std::io::Error::other(e)))??;
+    let (images, dimensions) = images;
+
+    let mut files = vec![];
+    for (part, (data, ext)) in images.iter().enumerate() {
+        let filename = node.naming.filename(node.page, node.episode, ext, part);
+        write_page(&path.join(&filename), data).map_err(ImageError::Io)?;
+        files.push(ManifestFile {
+            filename: manifest_filename(&filename),
+            hash: hash_bytes(data),
+        });
+    }
+    // There is always at least one image
+    let main = files.remove(0);
+
+    let page = ManifestPage {
+        index: node.idx,
+        episode: node.episode,
+        url: node.url.clone(),
+        filename: main.filename,
+        hash: main.hash,
+        extra_files: files,
+        encrypted_with: None,
+        dimensions,
+    };
+    manifest
+        .add_page(path, page)
+        .await
+        .map_err(ImageError::Io)?;
+
+    Ok(())
+}
+
+fn get_slice_image_count(prev_episodes: &[emancipated::models::ContentEpisodes]) -> usize {
     prev_episodes.iter().map(|e| e.pages.len()).sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(number: i32, purchased: bool, release_at: &str) -> Volume {
+        serde_json::from_value(serde_json::json!({
+            "slug": format!("volume-{}", number),
+            "volumeNumber": number,
+            "name": format!("Volume {}", number),
+            "purchased": purchased,
+            "readerSkipCover": false,
+            "releasesAt": release_at,
+            "cover": { "url": "https://example.com/cover.jpg", "height": 100 },
+        }))
+        .unwrap()
+    }
+
+    /// Volumes 1 to 5, 4 is not purchased and 5 is not released yet
+    fn volumes() -> Vec<Volume> {
+        vec![
+            volume(1, true, "2024-01-01T00:00:00Z"),
+            volume(2, true, "2024-02-01T00:00:00Z"),
+            volume(3, true, "2024-03-01T00:00:00Z"),
+            volume(4, false, "2024-04-01T00:00:00Z"),
+            volume(5, true, "2999-01-01T00:00:00Z"),
+        ]
+    }
+
+    fn resolve(selector: &str) -> (Vec<i32>, Vec<(i32, SkipReason)>) {
+        let (selected, skipped) = resolve_volumes(&volumes(), &selector.parse().unwrap());
+        (selected.iter().map(|v| v.number).collect(), skipped)
+    }
+
+    #[test]
+    fn test_resolve_all_and_latest() {
+        assert_eq!(
+            resolve("all"),
+            (vec![1, 2, 3], vec![(5, SkipReason::Unreleased)])
+        );
+        assert_eq!(
+            resolve("latest"),
+            (vec![], vec![(4, SkipReason::Unpurchased)])
+        );
+    }
+
+    #[test]
+    fn test_resolve_ranges() {
+        assert_eq!(resolve("1-2"), (vec![1, 2], vec![]));
+        assert_eq!(resolve("3,1"), (vec![1, 3], vec![]));
+        assert_eq!(
+            resolve("3-"),
+            (
+                vec![3],
+                vec![(4, SkipReason::Unpurchased), (5, SkipReason::Unreleased)]
+            )
+        );
+        // Overlapping ranges select each volume once
+        assert_eq!(
+            resolve("1-3,2-4,2"),
+            (vec![1, 2, 3], vec![(4, SkipReason::Unpurchased)])
+        );
+    }
+
+    #[test]
+    fn test_resolve_out_of_range() {
+        assert_eq!(resolve("0"), (vec![], vec![(0, SkipReason::Missing)]));
+        assert_eq!(resolve("2,9"), (vec![2], vec![(9, SkipReason::Missing)]));
+        // Ranges only select the volumes that exist
+        assert_eq!(resolve("8-10"), (vec![], vec![]));
+        assert_eq!(
+            resolve("3-10"),
+            (
+                vec![3],
+                vec![(4, SkipReason::Unpurchased), (5, SkipReason::Unreleased)]
+            )
+        );
+    }
+}
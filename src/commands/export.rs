@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use color_print::cformat;
+use emancipated::{
+    export::{
+        cbz::{export_cbz, export_cbz_episodes},
+        comicinfo::ComicInfo,
+        epub::export_epub,
+        pdf::export_pdf,
+    },
+    manifest::VolumeManifest,
+};
+
+use crate::cli::{DownloadFormat, ExitCode};
+
+/// Package the downloaded volume according to the selected format
+pub(crate) fn export_volume(
+    console: &crate::term::Terminal,
+    ep_dir: &Path,
+    manifest: &VolumeManifest,
+    info: &ComicInfo,
+    format: DownloadFormat,
+    per_episode: bool,
+) -> ExitCode {
+    let parent_dir = ep_dir.parent().unwrap_or(ep_dir);
+    let prefix = ep_dir
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("v{:02}", manifest.volume));
+
+    match format {
+        DownloadFormat::Folder => 0,
+        DownloadFormat::Cbz => {
+            if per_episode {
+                console.info("Packaging episodes into CBZ archives...");
+                match export_cbz_episodes(ep_dir, manifest, info, parent_dir, &prefix) {
+                    Ok(archives) => {
+                        console.info(cformat!(
+                            "Created <m,s>{}</> CBZ archives in <m,s>{}</>",
+                            archives.len(),
+                            parent_dir.display()
+                        ));
+                        0
+                    }
+                    Err(e) => {
+                        console.error(cformat!("Failed to create CBZ archives: <r,s>{}</>", e));
+                        1
+                    }
+                }
+            } else {
+                let target = parent_dir.join(format!("{}.cbz", prefix));
+                console.info("Packaging volume into CBZ archive...");
+                match export_cbz(ep_dir, manifest, info, &target) {
+                    Ok(_) => {
+                        console.info(cformat!("Created <m,s>{}</>", target.display()));
+                        0
+                    }
+                    Err(e) => {
+                        console.error(cformat!("Failed to create CBZ archive: <r,s>{}</>", e));
+                        1
+                    }
+                }
+            }
+        }
+        DownloadFormat::Epub => {
+            let target = parent_dir.join(format!("{}.epub", prefix));
+            console.info("Packaging volume into EPUB...");
+            match export_epub(ep_dir, manifest, &target) {
+                Ok(_) => {
+                    console.info(cformat!("Created <m,s>{}</>", target.display()));
+                    0
+                }
+                Err(e) => {
+                    console.error(cformat!("Failed to create EPUB: <r,s>{}</>", e));
+                    1
+                }
+            }
+        }
+        DownloadFormat::Pdf => {
+            let target = parent_dir.join(format!("{}.pdf", prefix));
+            console.info("Packaging volume into PDF...");
+            match export_pdf(ep_dir, manifest, &target) {
+                Ok(_) => {
+                    console.info(cformat!("Created <m,s>{}</>", target.display()));
+                    0
+                }
+                Err(e) => {
+                    console.error(cformat!("Failed to create PDF: <r,s>{}</>", e));
+                    1
+                }
+            }
+        }
+    }
+}
+
+/// Export an already downloaded volume directory
+pub(crate) fn volume_export(
+    console: &crate::term::Terminal,
+    directory: PathBuf,
+    format: DownloadFormat,
+    per_episode: bool,
+) -> ExitCode {
+    console.info(cformat!("Exporting <m,s>{}</>...", directory.display()));
+
+    let manifest = match VolumeManifest::load(&directory) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            console.error("No manifest found, is this a downloaded volume?");
+            return 1;
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to load manifest: <r,s>{}</>", e));
+            return 1;
+        }
+    };
+
+    let info = match (&manifest.comic, &manifest.volume_info) {
+        (Some(comic), Some(volume)) => ComicInfo::new(comic, volume),
+        _ => {
+            console
+                .error("The manifest does not have any metadata, please re-download the volume.");
+            return 1;
+        }
+    };
+
+    if format == DownloadFormat::Folder {
+        console.warn("Nothing to export for the folder format!");
+        return 1;
+    }
+
+    export_volume(console, &directory, &manifest, &info, format, per_episode)
+}
@@ -1,12 +1,10 @@
 use color_print::cformat;
 
-use crate::{
-    cli::ExitCode,
-    client::{Client, BASE_HOST},
-    config::save_config,
-    models::ComicTag,
-    term::macros::linkify,
-};
+use emancipated::{client::BASE_HOST, models::ComicTag, Client};
+
+use crate::{cli::ExitCode, term::macros::linkify};
+
+use super::accounts::save_client_config;
 
 pub(crate) async fn manga_search(
     client: &mut Client,
@@ -22,7 +20,7 @@ pub(crate) async fn manga_search(
 
     match client.search(&query).await {
         Ok(results) => {
-            save_config(client.get_config());
+            save_client_config(client, console);
 
             if results.is_empty() {
                 console.info("No results found!");
@@ -72,6 +70,7 @@ fn format_tags(tags: Vec<ComicTag>) -> String {
     format_vec_comma(parsed_tags)
 }
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 pub(crate) async fn manga_info(
     client: &mut Client,
     console: &crate::term::Terminal,
@@ -85,7 +84,7 @@ pub(crate) async fn manga_info(
 
     match client.get_volumes(&slug).await {
         Ok(comic) => {
-            save_config(client.get_config());
+            save_client_config(client, console);
 
             let manga_url = format!("https://{}/{}", &*BASE_HOST, comic.comic.slug);
             let linked = linkify!(manga_url, &comic.comic.title);
@@ -144,3 +143,56 @@ pub(crate) async fn manga_info(
         }
     }
 }
+
+pub(crate) async fn manga_purchased(
+    client: &mut Client,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    console.info("Fetching your purchased titles...");
+
+    match client.get_purchased().await {
+        Ok(library) => {
+            save_client_config(client, console);
+
+            if library.is_empty() {
+                console.info("You have not purchased anything yet!");
+                return 0;
+            }
+
+            console.info(cformat!(
+                "Found <m,s>{}</> purchased titles:",
+                library.len()
+            ));
+
+            for (i, result) in library.iter().enumerate() {
+                let manga_url = format!("https://{}/{}", &*BASE_HOST, result.comic.slug);
+                let linked = linkify!(manga_url, &result.comic.title);
+
+                let text_data = cformat!("<s>{}</> ({})", linked, result.comic.slug);
+                console.info(format!("  [{:02}] {}", i + 1, text_data));
+
+                let mut volumes: Vec<i32> = result.volumes.iter().map(|v| v.number).collect();
+                volumes.sort();
+                volumes.dedup();
+
+                if volumes.is_empty() {
+                    console.info(cformat!("       <s>Volumes</>: <y,s>None</>"));
+                } else {
+                    let joined_volumes =
+                        format_vec_comma(volumes.iter().map(|v| format!("#{}", v)).collect());
+                    console.info(cformat!(
+                        "       <s>Volumes</>: {} ({} owned)",
+                        joined_volumes,
+                        volumes.len()
+                    ));
+                }
+            }
+
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to fetch purchased titles: <r,s>{}</>", e));
+            1
+        }
+    }
+}
@@ -1,3 +1,7 @@
 pub mod accounts;
+pub mod decrypt;
+pub mod doctor;
 pub mod download;
+pub mod export;
 pub mod manga;
+pub mod settings;
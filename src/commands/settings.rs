@@ -0,0 +1,154 @@
+use clap::ValueEnum;
+use color_print::cformat;
+
+use emancipated::settings::{Settings, SettingsValues};
+
+use crate::cli::{CoverMode, DownloadFormat, DownloadLayout, ExitCode};
+
+/// Parse the format from the settings
+pub(crate) fn parse_format(format: &str) -> Result<DownloadFormat, String> {
+    DownloadFormat::from_str(format, true)
+}
+
+/// Parse the layout from the settings
+pub(crate) fn parse_layout(layout: &str) -> Result<DownloadLayout, String> {
+    DownloadLayout::from_str(layout, true)
+}
+
+/// Parse the cover mode from the settings
+pub(crate) fn parse_cover(cover: &str) -> Result<CoverMode, String> {
+    CoverMode::from_str(cover, true)
+}
+
+/// Resolve the effective settings for the account
+///
+/// Invalid environment variables are ignored with a warning, so they do not break the unrelated commands.
+pub(crate) fn resolve_settings(
+    settings: &Settings,
+    account: Option<&str>,
+    console: &crate::term::Terminal,
+) -> SettingsValues {
+    let (values, errors) = settings.resolve_lossy(account);
+    for e in errors {
+        console.warn(cformat!("Ignoring invalid setting: <y,s>{}</>", e));
+    }
+
+    values
+}
+
+fn print_values(values: &SettingsValues, console: &crate::term::Terminal) {
+    let mut empty = true;
+    for key in SettingsValues::KEYS {
+        if let Some(value) = values.get(key) {
+            console.info(cformat!("  - <s>{}</>: {}", key, value));
+            empty = false;
+        }
+    }
+
+    if empty {
+        console.info("  No settings");
+    }
+}
+
+pub(crate) fn settings_show(
+    settings: &Settings,
+    account: Option<&str>,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    if let Ok(path) = Settings::path() {
+        console.info(cformat!("Settings file: <m,s>{}</>", path.display()));
+    }
+
+    console.info("Global settings:");
+    print_values(&settings.defaults, console);
+
+    for (email, values) in settings.accounts.iter() {
+        if account.is_none() || account == Some(email.as_str()) {
+            console.info(cformat!("Settings for <m,s>{}</>:", email));
+            print_values(values, console);
+        }
+    }
+
+    let values = resolve_settings(settings, account, console);
+    console.info("Effective settings (including environment variables):");
+    print_values(&values, console);
+    0
+}
+
+pub(crate) fn settings_set(
+    mut settings: Settings,
+    account: Option<&str>,
+    key: &str,
+    value: &str,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    if key == "format" {
+        if let Err(e) = parse_format(value) {
+            console.error(cformat!("Invalid format: <r,s>{}</>", e));
+            return 1;
+        }
+    }
+    if key == "layout" {
+        if let Err(e) = parse_layout(value) {
+            console.error(cformat!("Invalid layout: <r,s>{}</>", e));
+            return 1;
+        }
+    }
+    if key == "cover" {
+        if let Err(e) = parse_cover(value) {
+            console.error(cformat!("Invalid cover mode: <r,s>{}</>", e));
+            return 1;
+        }
+    }
+
+    if let Err(e) = settings.values_mut(account).set(key, value) {
+        console.error(cformat!("<r,s>{}</>", e));
+        return 1;
+    }
+
+    save_settings(&settings, account, key, console)
+}
+
+pub(crate) fn settings_unset(
+    mut settings: Settings,
+    account: Option<&str>,
+    key: &str,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    if let Err(e) = settings.values_mut(account).unset(key) {
+        console.error(cformat!("<r,s>{}</>", e));
+        return 1;
+    }
+
+    // Clean up the empty account table
+    if let Some(account) = account {
+        if settings.accounts.get(account) == Some(&SettingsValues::default()) {
+            settings.accounts.remove(account);
+        }
+    }
+
+    save_settings(&settings, account, key, console)
+}
+
+fn save_settings(
+    settings: &Settings,
+    account: Option<&str>,
+    key: &str,
+    console: &crate::term::Terminal,
+) -> ExitCode {
+    match settings.save() {
+        Ok(_) => {
+            match account {
+                Some(account) => {
+                    console.info(cformat!("Updated <s>{}</> for <m,s>{}</>", key, account))
+                }
+                None => console.info(cformat!("Updated <s>{}</>", key)),
+            }
+            0
+        }
+        Err(e) => {
+            console.error(cformat!("Failed to save settings: <r,s>{}</>", e));
+            1
+        }
+    }
+}
@@ -1,16 +1,28 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
+use aes_gcm::{
+    aead::{Aead, AeadCore},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
 use directories::BaseDirs;
+use rand::RngCore;
 use rsa::{RsaPrivateKey, RsaPublicKey};
 use serde::{Deserialize, Serialize};
 
-use crate::{
-    kp::{self, hash_b64},
-    term::ConsoleChoice,
-};
+use crate::kp::{self, hash_b64};
+
+/// The current version of the config file, bump this and add a migration when changing [`Config`]
+pub const CONFIG_VERSION: u64 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// The version of the config file, see [`migrate_config`]
+    version: u64,
     email: String,
     access_token: String,
     refresh_token: String,
@@ -27,6 +39,10 @@ impl Config {
         self.expires_at < unix_time
     }
 
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     pub fn email(&self) -> &str {
         &self.email
     }
@@ -59,8 +75,16 @@ impl Config {
         PathBuf::from(&self.private_key)
     }
 
-    pub fn get_key_pair(&self) -> Result<(RsaPrivateKey, RsaPublicKey), kp::RSAError> {
-        kp::load_key_pair(&self.private_key(), &self.public_key())
+    /// Load the key pair, decrypting the private key with the passphrase if needed
+    pub fn get_key_pair(
+        &self,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<(RsaPrivateKey, RsaPublicKey), kp::RSAError> {
+        kp::load_key_pair(
+            &self.private_key(),
+            &self.public_key(),
+            passphrase.map(Passphrase::as_str),
+        )
     }
 
     pub fn set_public_key(&mut self, public_key: &str) {
@@ -75,24 +99,27 @@ impl Config {
         !self.public_key.is_empty() && !self.private_key.is_empty()
     }
 
-    pub fn generate_key_pair(&mut self) -> Result<(RsaPrivateKey, RsaPublicKey), kp::RSAError> {
-        let write_dir = get_user_path();
+    /// Generate and write a new key pair, encrypting the private key if the passphrase is given
+    pub fn generate_key_pair(
+        &mut self,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<(RsaPrivateKey, RsaPublicKey), ConfigError> {
+        let write_dir = get_user_path()?;
+        std::fs::create_dir_all(&write_dir)?;
         let (private_key, public_key) = kp::generate_key_pair()?;
 
-        let email_64 = hash_b64(&self.email);
-
-        let private_key_path = write_dir.join(format!("{}_kp.pem", email_64));
-        let public_key_path = write_dir.join(format!("{}_kp.pub", email_64));
+        let (private_key_path, public_key_path) = kp::key_pair_paths(&write_dir, &self.email);
 
         kp::write_key_pair(
             &private_key_path,
             &public_key_path,
             &private_key,
             &public_key,
+            passphrase.map(Passphrase::as_str),
         )?;
 
-        self.set_private_key(private_key_path.to_str().unwrap());
-        self.set_public_key(public_key_path.to_str().unwrap());
+        self.set_private_key(&private_key_path.to_string_lossy());
+        self.set_public_key(&public_key_path.to_string_lossy());
 
         Ok((private_key, public_key))
     }
@@ -101,6 +128,7 @@ impl Config {
 impl From<google_auth::VerifyPasswordResponseMinimal> for Config {
     fn from(value: google_auth::VerifyPasswordResponseMinimal) -> Self {
         Self {
+            version: CONFIG_VERSION,
             email: value.email.clone(),
             access_token: value.id_token.clone(),
             refresh_token: value.refresh_token.clone(),
@@ -114,6 +142,7 @@ impl From<google_auth::VerifyPasswordResponseMinimal> for Config {
 impl From<&google_auth::VerifyPasswordResponseMinimal> for Config {
     fn from(value: &google_auth::VerifyPasswordResponseMinimal) -> Self {
         Self {
+            version: CONFIG_VERSION,
             email: value.email.clone(),
             access_token: value.id_token.clone(),
             refresh_token: value.refresh_token.clone(),
@@ -124,148 +153,684 @@ impl From<&google_auth::VerifyPasswordResponseMinimal> for Config {
     }
 }
 
-pub(crate) fn get_user_path() -> std::path::PathBuf {
-    #[cfg(windows)]
-    let user_path = {
-        let mut local_appdata: std::path::PathBuf =
-            BaseDirs::new().unwrap().config_local_dir().to_path_buf();
-        local_appdata.push("EmancipatedRs");
-        local_appdata
-    };
-    #[cfg(not(windows))]
-    let user_path: std::path::PathBuf = {
-        let mut home = BaseDirs::new().unwrap().home_dir().to_path_buf();
-        home.push(".emancipatedrs");
-        home
-    };
-    user_path
-}
-
-pub(crate) fn find_any_config() -> Vec<Config> {
-    let user_path = get_user_path();
-    let mut configs = vec![];
-
-    if user_path.exists() {
-        // glob dir for config_*.json
-        let read_dir = std::fs::read_dir(user_path).unwrap();
-        for file in read_dir {
-            let file = file.unwrap();
-            let path = file.path();
-            if path.is_file() {
-                let file_name = path.file_name().unwrap().to_str().unwrap();
-                if file_name.starts_with("config_") && file_name.ends_with(".json") {
-                    let file = std::fs::File::open(path).unwrap();
-                    let config: Config = serde_json::from_reader(file).unwrap();
-                    configs.push(config);
-                }
+/// Error type for reading and writing the accounts
+pub enum ConfigError {
+    Io(std::io::Error),
+    Serde(serde_json::Error),
+    RSA(kp::RSAError),
+    /// The home directory cannot be determined
+    NoHomeDirectory,
+    /// The config file is malformed
+    Corrupted(PathBuf, serde_json::Error),
+    /// The config file is encrypted and the passphrase is missing or wrong
+    Locked(PathBuf),
+    /// The config file is from a newer version
+    UnsupportedVersion(u64),
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Serde(e)
+    }
+}
+
+impl From<kp::RSAError> for ConfigError {
+    fn from(e: kp::RSAError) -> Self {
+        Self::RSA(e)
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Serde(e) => write!(f, "Serde Error: {}", e),
+            Self::RSA(e) => write!(f, "RSA Error: {}", e),
+            Self::NoHomeDirectory => write!(f, "Unable to find the home directory"),
+            Self::Corrupted(path, e) => {
+                write!(f, "Corrupted config file {}: {}", path.display(), e)
             }
+            Self::Locked(path) => write!(
+                f,
+                "Config file {} is encrypted, invalid or missing passphrase",
+                path.display()
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Config version {} is not supported, the latest is {}",
+                version, CONFIG_VERSION
+            ),
         }
     }
+}
 
-    configs
+impl std::fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {:?}", e),
+            Self::Serde(e) => write!(f, "Serde Error: {:?}", e),
+            Self::RSA(e) => write!(f, "RSA Error: {:?}", e),
+            Self::NoHomeDirectory => write!(f, "Unable to find the home directory"),
+            Self::Corrupted(path, e) => {
+                write!(f, "Corrupted config file {}: {:?}", path.display(), e)
+            }
+            Self::Locked(path) => write!(f, "Config file {} is locked", path.display()),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported config version {}", version)
+            }
+        }
+    }
 }
 
-pub(crate) fn get_config(email: impl Into<String>) -> Option<Config> {
+/// Environment variable to override the user path
+pub const USER_PATH_ENV: &str = "EMANCIPATED_HOME";
+
+/// Get the path where the accounts and keys are stored
+///
+/// Can be overridden with the `EMANCIPATED_HOME` environment variable.
+pub fn get_user_path() -> Result<PathBuf, ConfigError> {
+    if let Some(user_path) = std::env::var_os(USER_PATH_ENV) {
+        if !user_path.is_empty() {
+            return Ok(PathBuf::from(user_path));
+        }
+    }
+
+    let base_dirs = BaseDirs::new().ok_or(ConfigError::NoHomeDirectory)?;
+
+    #[cfg(windows)]
+    let user_path = base_dirs.config_local_dir().join("EmancipatedRs");
+    #[cfg(not(windows))]
+    let user_path = base_dirs.home_dir().join(".emancipatedrs");
+
+    Ok(user_path)
+}
+
+/// A config file found in the user path
+pub struct ConfigFile {
+    /// The path of the config file
+    pub path: PathBuf,
+    /// The parsed config, or why it failed to load
+    pub config: Result<Config, ConfigError>,
+    /// Whether the file is older than [`CONFIG_VERSION`] and was only migrated in memory
+    pub outdated: bool,
+}
+
+/// Read every config file in the user path, including the broken ones
+///
+/// The encrypted files are decrypted with the passphrase, or reported as locked without it.
+pub fn scan_configs(passphrase: Option<&Passphrase>) -> Result<Vec<ConfigFile>, ConfigError> {
+    let configs = config_files()?
+        .into_iter()
+        .map(|path| match read_config_file(&path, passphrase) {
+            Ok((config, outdated)) => ConfigFile {
+                path,
+                config: Ok(config),
+                outdated,
+            },
+            Err(e) => ConfigFile {
+                path,
+                config: Err(e),
+                outdated: false,
+            },
+        })
+        .collect();
+
+    Ok(configs)
+}
+
+/// Get all the valid accounts
+///
+/// Broken and locked config files are skipped, use [`scan_configs`] to find them.
+pub fn find_any_config(passphrase: Option<&Passphrase>) -> Result<Vec<Config>, ConfigError> {
+    let configs = scan_configs(passphrase)?
+        .into_iter()
+        .filter_map(|file| file.config.ok())
+        .collect();
+
+    Ok(configs)
+}
+
+/// Get the account by the email, returns `None` if it does not exist
+pub fn get_config(
+    email: impl Into<String>,
+    passphrase: Option<&Passphrase>,
+) -> Result<Option<Config>, ConfigError> {
     let email = email.into();
-    let user_path = get_user_path();
-    let email_64 = hash_b64(&email);
-    let file_path = user_path.join(format!("config_{}.json", email_64));
+    let file_path = get_config_path(&email)?;
 
     if file_path.exists() {
-        let file = std::fs::File::open(file_path).unwrap();
-        let config: Config = serde_json::from_reader(file).unwrap();
-        return Some(config);
+        return read_config_file(&file_path, passphrase).map(|(config, _)| Some(config));
     }
 
-    None
+    Ok(None)
 }
 
-pub(crate) fn select_single_account(
-    email: Option<&str>,
-    term: &crate::term::Terminal,
-) -> Option<Config> {
-    if let Some(email) = email {
-        let config = get_config(email);
+/// Get the path of the config file for the email
+pub fn get_config_path(email: &str) -> Result<PathBuf, ConfigError> {
+    Ok(get_user_path()?.join(format!("config_{}.json", hash_b64(email))))
+}
 
-        if let Some(config) = config {
-            return Some(config.clone());
+/// Remove the account config and its key pair
+///
+/// The config is not read, so locked and broken accounts can be removed too.
+/// Returns `false` if the account does not exist.
+pub fn remove_config(email: &str) -> Result<bool, ConfigError> {
+    let config_path = get_config_path(email)?;
+    if !config_path.exists() {
+        return Ok(false);
+    }
+
+    let (private_key, public_key) = kp::key_pair_paths(&get_user_path()?, email);
+    for key_path in [private_key, public_key] {
+        if key_path.exists() {
+            std::fs::remove_file(key_path)?;
         }
+    }
+    std::fs::remove_file(config_path)?;
 
-        term.warn(&format!("Account ID {} not found!", email));
+    if get_default_account().as_deref() == Some(email) {
+        set_default_account(None)?;
+    }
 
-        return None;
+    Ok(true)
+}
+
+/// Move a broken file into the `quarantine` folder in the user path
+///
+/// Returns the new path of the file.
+pub fn quarantine_file(path: &Path) -> Result<PathBuf, ConfigError> {
+    let quarantine_dir = get_user_path()?.join("quarantine");
+    std::fs::create_dir_all(&quarantine_dir)?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
+    let target = quarantine_dir.join(format!("{}.{}", file_name, timestamp));
+    std::fs::rename(path, &target)?;
+
+    Ok(target)
+}
+
+/// The file that stores the email of the default account
+pub const DEFAULT_ACCOUNT_FILE: &str = "default_account";
+
+/// Get the email of the default account, if any
+pub fn get_default_account() -> Option<String> {
+    let content = std::fs::read_to_string(get_user_path().ok()?.join(DEFAULT_ACCOUNT_FILE)).ok()?;
+    let email = content.trim();
+
+    if email.is_empty() {
+        None
+    } else {
+        Some(email.to_string())
     }
+}
 
-    let all_configs = find_any_config();
-    let all_choices: Vec<ConsoleChoice> = all_configs
-        .iter()
-        .map(|c| ConsoleChoice {
-            name: c.email().to_string(),
-            value: c.email().to_string(),
-        })
-        .collect();
+/// Set or clear the default account
+pub fn set_default_account(email: Option<&str>) -> Result<(), ConfigError> {
+    let user_path = get_user_path()?;
+    let file_path = user_path.join(DEFAULT_ACCOUNT_FILE);
 
-    if all_configs.is_empty() {
-        term.warn("No accounts found!");
-        return None;
+    match email {
+        Some(email) => {
+            std::fs::create_dir_all(&user_path)?;
+            write_atomic(&file_path, email.as_bytes())?;
+        }
+        None if file_path.exists() => std::fs::remove_file(file_path)?,
+        None => {}
     }
 
-    // only 1? return
-    if all_configs.len() == 1 {
-        return Some(all_configs[0].clone());
+    Ok(())
+}
+
+/// Save the config, encrypting it if the passphrase is given
+pub fn save_config(config: &Config, passphrase: Option<&Passphrase>) -> Result<(), ConfigError> {
+    let user_path = get_user_path()?;
+    if !user_path.exists() {
+        std::fs::create_dir_all(&user_path)?;
     }
 
-    let selected = term.choice("Select an account:", all_choices);
-    match selected {
-        Some(selected) => {
-            let config = all_configs
-                .iter()
-                .find(|c| c.email() == selected.name)
-                .unwrap()
-                .clone();
+    write_config_file(&get_config_path(&config.email)?, config, passphrase)
+}
 
-            Some(config)
+/// Write the config, encrypting it if the passphrase is given
+fn write_config_file(
+    file_path: &Path,
+    config: &Config,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), ConfigError> {
+    let results = match passphrase {
+        Some(passphrase) => {
+            let encrypted = EncryptedConfig::encrypt(config, passphrase)?;
+            serde_json::to_string_pretty(&encrypted)?
+        }
+        None => serde_json::to_string_pretty(config)?,
+    };
+
+    write_private_file(file_path, results.as_bytes())?;
+
+    Ok(())
+}
+
+/// Get the temporary path used while writing the file atomically
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.tmp", file_name))
+}
+
+fn write_atomic_with_mode(path: &Path, data: &[u8], private: bool) -> Result<(), std::io::Error> {
+    let temp_path = temporary_path(path);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let result = options.open(&temp_path).and_then(|mut file| {
+        // The mode only applies to new files, so fix up leftovers too
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+
+        file.write_all(data)?;
+        file.sync_all()
+    });
+
+    match result.and_then(|_| std::fs::rename(&temp_path, path)) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
         }
-        None => None,
     }
 }
 
-pub(crate) fn save_config(config: &Config) {
-    let user_path = get_user_path();
+/// Write the file atomically by writing to a temporary file first then renaming it
+///
+/// A crash in the middle of writing will never leave a partially written file behind.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    write_atomic_with_mode(path, data, false)
+}
+
+/// Write a file atomically that is only readable by the current user on Unix
+pub fn write_private_file(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    write_atomic_with_mode(path, data, true)
+}
+
+/// A key derived from the passphrase, see [`EncryptedConfig::derive_key`]
+struct DerivedKey {
+    salt: Vec<u8>,
+    key: [u8; 32],
+}
+
+/// The passphrase used to encrypt the accounts and keys
+///
+/// scrypt is slow on purpose, so the keys derived from it are cached in the handle and shared by its clones.
+#[derive(Clone)]
+pub struct Passphrase {
+    passphrase: String,
+    derived_keys: Arc<RwLock<Vec<DerivedKey>>>,
+}
+
+impl Passphrase {
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            derived_keys: Arc::new(RwLock::new(vec![])),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.passphrase
+    }
+}
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Passphrase(<redacted>)")
+    }
+}
+
+/// Check if any of the stored accounts is encrypted
+pub fn has_encrypted_config() -> bool {
+    config_files()
+        .unwrap_or_default()
+        .iter()
+        .any(|path| read_encrypted_config(path).is_some())
+}
+
+/// Try to unlock the encrypted accounts with the passphrase
+///
+/// Returns `None` if it cannot decrypt the stored accounts.
+pub fn unlock(passphrase: &str) -> Option<Passphrase> {
+    let passphrase = Passphrase::new(passphrase);
+    let encrypted = config_files()
+        .unwrap_or_default()
+        .iter()
+        .find_map(|path| read_encrypted_config(path));
+
+    match encrypted {
+        Some(encrypted) => encrypted.decrypt(&passphrase).map(|_| passphrase),
+        None => Some(passphrase),
+    }
+}
+
+/// Change the passphrase of all the accounts and private keys
+///
+/// `None` will remove the encryption. The current passphrase is required if the accounts are encrypted.
+///
+/// Returns the number of accounts that got re-encrypted. Nothing is changed if any account
+/// cannot be read, e.g. it is locked with another passphrase or corrupted.
+pub fn change_passphrase(
+    passphrase: Option<&Passphrase>,
+    new_passphrase: Option<&Passphrase>,
+) -> Result<usize, ConfigError> {
+    let configs = scan_configs(passphrase)?
+        .into_iter()
+        .map(|file| file.config)
+        .collect::<Result<Vec<Config>, ConfigError>>()?;
+
+    // Load everything with the current passphrase first
+    let mut key_pairs = vec![];
+    for config in configs.iter() {
+        if config.has_key() {
+            key_pairs.push(Some(config.get_key_pair(passphrase)?));
+        } else {
+            key_pairs.push(None);
+        }
+    }
+
+    for (config, key_pair) in configs.iter().zip(key_pairs) {
+        if let Some((private_key, public_key)) = key_pair {
+            kp::write_key_pair(
+                &config.private_key(),
+                &config.public_key(),
+                &private_key,
+                &public_key,
+                new_passphrase.map(Passphrase::as_str),
+            )?;
+        }
+        save_config(config, new_passphrase)?;
+    }
+
+    Ok(configs.len())
+}
+
+/// Get all the config files in the user path
+fn config_files() -> Result<Vec<PathBuf>, ConfigError> {
+    let user_path = get_user_path()?;
     if !user_path.exists() {
-        std::fs::create_dir_all(&user_path).unwrap();
+        return Ok(vec![]);
+    }
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(user_path)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && file_name.starts_with("config_") && file_name.ends_with(".json") {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+fn read_encrypted_config(path: &Path) -> Option<EncryptedConfig> {
+    let file = std::fs::File::open(path).ok()?;
+    serde_json::from_reader(std::io::BufReader::new(file)).ok()
+}
+
+/// Read the raw config file, decrypting it with the passphrase if needed
+///
+/// Returns the raw config and whether the file is encrypted.
+fn read_config_value(
+    path: &Path,
+    passphrase: Option<&Passphrase>,
+) -> Result<(serde_json::Value, bool), ConfigError> {
+    let content = std::fs::read_to_string(path)?;
+
+    match serde_json::from_str::<EncryptedConfig>(&content) {
+        Ok(encrypted) => passphrase
+            .and_then(|passphrase| encrypted.decrypt(passphrase))
+            .map(|value| (value, true))
+            .ok_or_else(|| ConfigError::Locked(path.to_path_buf())),
+        Err(_) => serde_json::from_str(&content)
+            .map(|value| (value, false))
+            .map_err(|e| ConfigError::Corrupted(path.to_path_buf(), e)),
+    }
+}
+
+/// Read the config file and migrate it in memory
+///
+/// Returns the config and whether the file is outdated, the file itself is never written.
+fn read_config_file(
+    path: &Path,
+    passphrase: Option<&Passphrase>,
+) -> Result<(Config, bool), ConfigError> {
+    let (value, _) = read_config_value(path, passphrase)?;
+
+    migrate_config(value).map_err(|e| match e {
+        ConfigError::Serde(e) => ConfigError::Corrupted(path.to_path_buf(), e),
+        e => e,
+    })
+}
+
+/// Write the migrated config back to an outdated config file
+///
+/// The file is kept encrypted or in plaintext as it was. Returns `false` if it is already up to date.
+pub fn upgrade_config_file(
+    path: &Path,
+    passphrase: Option<&Passphrase>,
+) -> Result<bool, ConfigError> {
+    let (value, encrypted) = read_config_value(path, passphrase)?;
+    let (config, migrated) = migrate_config(value).map_err(|e| match e {
+        ConfigError::Serde(e) => ConfigError::Corrupted(path.to_path_buf(), e),
+        e => e,
+    })?;
+
+    if migrated {
+        let passphrase = if encrypted { passphrase } else { None };
+        write_config_file(path, &config, passphrase)?;
+    }
+
+    Ok(migrated)
+}
+
+/// A single migration step, upgrading the raw config by one version
+pub type Migration = fn(&mut serde_json::Map<String, serde_json::Value>);
+
+/// The migrations in order, starting from version 1 to 2
+///
+/// Version 0 is the original unversioned config, it has the same fields as version 1
+/// so upgrading it only stamps the version.
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize - 1] = [];
+
+/// Upgrade the raw config to the [`CONFIG_VERSION`] and parse it
+///
+/// A config without the `version` field is treated as version 0.
+/// Returns the config and whether any migration was applied.
+pub fn migrate_config(value: serde_json::Value) -> Result<(Config, bool), ConfigError> {
+    let mut value = match value {
+        serde_json::Value::Object(value) => value,
+        other => return Ok((serde_json::from_value(other)?, false)),
+    };
+
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| serde::de::Error::custom("version must be an unsigned integer"))
+            .map_err(ConfigError::Serde)?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::UnsupportedVersion(version));
+    }
+
+    migrate(&mut value, version, &MIGRATIONS);
+
+    let config = serde_json::from_value(serde_json::Value::Object(value))?;
+    Ok((config, version < CONFIG_VERSION))
+}
+
+/// Run the migrations after the `version` on the raw config, then stamp the latest version
+///
+/// `migrations[0]` upgrades version 1 to 2, so the latest version is one more than the number of migrations.
+pub fn migrate(
+    value: &mut serde_json::Map<String, serde_json::Value>,
+    version: u64,
+    migrations: &[Migration],
+) {
+    for migration in migrations.iter().skip(version.saturating_sub(1) as usize) {
+        migration(value);
+    }
+    value.insert("version".to_string(), (migrations.len() as u64 + 1).into());
+}
+
+/// The on-disk format of a passphrase protected config
+///
+/// The key is derived from the passphrase with scrypt, then the config is encrypted with AES-256-GCM.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptedConfig {
+    email: String,
+    encrypted: EncryptedData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptedData {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedConfig {
+    /// Derive the AES key from the passphrase, returns `None` if the scrypt parameters are invalid
+    ///
+    /// The key is cached in the passphrase handle.
+    fn derive_key(passphrase: &Passphrase, salt: &[u8]) -> Option<[u8; 32]> {
+        let cached = passphrase
+            .derived_keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|d| d.salt == salt)
+            .map(|d| d.key);
+        if cached.is_some() {
+            return cached;
+        }
+
+        let mut key = [0u8; 32];
+        let params = scrypt::Params::new(15, 8, 1, key.len()).ok()?;
+        scrypt::scrypt(passphrase.as_str().as_bytes(), salt, &params, &mut key).ok()?;
+        passphrase.derived_keys.write().unwrap().push(DerivedKey {
+            salt: salt.to_vec(),
+            key,
+        });
+        Some(key)
     }
 
-    let email_64 = hash_b64(&config.email);
-    let file_path = user_path.join(format!("config_{}.json", email_64));
-    let mut file = std::fs::File::create(file_path).unwrap();
+    /// Reuse the salt of a key already derived from the passphrase, or make a new one
+    fn session_salt(passphrase: &Passphrase) -> Vec<u8> {
+        let cached = passphrase
+            .derived_keys
+            .read()
+            .unwrap()
+            .first()
+            .map(|d| d.salt.clone());
+
+        cached.unwrap_or_else(|| {
+            let mut salt = vec![0u8; 16];
+            rand::thread_rng().fill_bytes(&mut salt);
+            salt
+        })
+    }
 
-    let results = serde_json::to_string_pretty(config).unwrap();
+    fn encrypt(config: &Config, passphrase: &Passphrase) -> Result<Self, ConfigError> {
+        // The nonce is always new, so the salt and key can be shared by every save
+        let salt = Self::session_salt(passphrase);
+        let key = Self::derive_key(passphrase, &salt)
+            .ok_or_else(|| std::io::Error::other("failed to derive the config key"))?;
+
+        let cipher = Aes256Gcm::new(&key.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut rand::thread_rng());
+        let plaintext = serde_json::to_vec(config)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| std::io::Error::other("failed to encrypt the config"))?;
+
+        Ok(Self {
+            email: config.email.clone(),
+            encrypted: EncryptedData {
+                salt: general_purpose::STANDARD.encode(salt),
+                nonce: general_purpose::STANDARD.encode(nonce),
+                ciphertext: general_purpose::STANDARD.encode(ciphertext),
+            },
+        })
+    }
 
-    file.write_all(results.as_bytes()).unwrap();
+    /// Decrypt into the raw config, which might need to be migrated
+    ///
+    /// Returns `None` if the passphrase is wrong.
+    fn decrypt(&self, passphrase: &Passphrase) -> Option<serde_json::Value> {
+        let salt = general_purpose::STANDARD
+            .decode(&self.encrypted.salt)
+            .ok()?;
+        let nonce = general_purpose::STANDARD
+            .decode(&self.encrypted.nonce)
+            .ok()?;
+        let ciphertext = general_purpose::STANDARD
+            .decode(&self.encrypted.ciphertext)
+            .ok()?;
+        if nonce.len() != 12 {
+            return None;
+        }
+
+        let key = Self::derive_key(passphrase, &salt)?;
+        let cipher = Aes256Gcm::new(&key.into());
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .ok()?;
+
+        serde_json::from_slice(&plaintext).ok()
+    }
 }
 
-pub(crate) mod google_auth {
+pub mod google_auth {
     use serde::{Deserialize, Serialize};
 
     /// Object representing the response of the auth request.
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct VerifyPasswordResponseMinimal {
         #[serde(rename = "idToken")]
-        pub(crate) id_token: String,
+        pub id_token: String,
         #[serde(rename = "expiresIn")]
-        pub(crate) expires_in: String,
+        pub expires_in: String,
         #[serde(rename = "refreshToken")]
-        pub(crate) refresh_token: String,
-        pub(crate) email: String,
+        pub refresh_token: String,
+        pub email: String,
     }
 
     impl VerifyPasswordResponseMinimal {
+        /// The unix timestamp when the token expires
+        ///
+        /// An invalid `expiresIn` is treated as already expired, so the token is refreshed on the next use.
         pub fn expires_at(&self) -> i64 {
-            let expires_in: i64 = self.expires_in.parse().unwrap();
             let unix_time = time::OffsetDateTime::now_utc().unix_timestamp();
-
-            unix_time + expires_in
+            match self.expires_in.parse::<i64>() {
+                Ok(expires_in) => unix_time + expires_in,
+                Err(_) => 0,
+            }
         }
     }
 
@@ -282,11 +847,15 @@ pub(crate) mod google_auth {
     }
 
     impl SecureTokenResponse {
+        /// The unix timestamp when the token expires
+        ///
+        /// An invalid `expiresIn` is treated as already expired, so the token is refreshed on the next use.
         pub fn expires_at(&self) -> i64 {
-            let expires_in: i64 = self.expires_in.parse().unwrap();
             let unix_time = time::OffsetDateTime::now_utc().unix_timestamp();
-
-            unix_time + expires_in
+            match self.expires_in.parse::<i64>() {
+                Ok(expires_in) => unix_time + expires_in,
+                Err(_) => 0,
+            }
         }
     }
 }
@@ -0,0 +1,51 @@
+//! Decrypt the pages that were downloaded and kept encrypted.
+//!
+//! The raw pages are saved with the [`ENCRYPTED_EXTENSION`] and the volume hash is recorded
+//! in the manifest, so they can be decrypted later with the private key of the account
+//! without downloading them again.
+
+use std::path::Path;
+
+use crate::{
+    image::{decode_image, decrypt_image, image_dimensions, ImageError},
+    manifest::{hash_bytes, ManifestPage},
+};
+
+/// The extension of the pages that are kept encrypted
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Decrypt the encrypted page in place and update the manifest entry
+///
+/// The decrypted page is saved next to the encrypted file with the correct extension,
+/// then the encrypted file is removed. The cover is used as is if it is not encrypted.
+///
+/// Does nothing if the page is not encrypted.
+pub fn decrypt_page(
+    directory: &Path,
+    page: &mut ManifestPage,
+    aes_key: &[u8],
+) -> Result<(), ImageError> {
+    if page.encrypted_with.is_none() {
+        return Ok(());
+    }
+
+    let encrypted_path = directory.join(&page.filename);
+    let data = std::fs::read(&encrypted_path)?;
+    let (decrypted, ext) = match page.episode {
+        Some(_) => decrypt_image(&data, aes_key)?,
+        None => decode_image(&data, aes_key)?,
+    };
+
+    let filename = Path::new(&page.filename).with_extension(ext);
+    crate::config::write_atomic(&directory.join(&filename), &decrypted)?;
+    if filename != Path::new(&page.filename) {
+        std::fs::remove_file(&encrypted_path)?;
+    }
+
+    page.filename = filename.to_string_lossy().replace('\\', "/");
+    page.hash = hash_bytes(&decrypted);
+    page.encrypted_with = None;
+    page.dimensions = image_dimensions(&decrypted).ok();
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! Prepare the pages for e-readers.
+//!
+//! Each page can be trimmed, split into two when it is a landscape spread, resized to fit the device,
+//! and adjusted with gamma and contrast since e-ink screens tend to look washed out.
+//!
+//! ```
+//! use emancipated::device::{find_profile, process_page, DeviceOptions, SplitOrder};
+//!
+//! let profile = find_profile("kobo-clara").unwrap();
+//! let options = DeviceOptions {
+//!     split: Some(SplitOrder::RightToLeft),
+//!     ..DeviceOptions::from_profile(profile)
+//! };
+//!
+//! let spread = image::DynamicImage::new_rgb8(3000, 2000);
+//! let pages = process_page(spread, &options);
+//! assert_eq!(pages.len(), 2);
+//! assert!(pages.iter().all(|p| p.width() <= 1072 && p.height() <= 1448));
+//! ```
+
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Pixel};
+
+use crate::{
+    image::ImageError,
+    transcode::{encode, to_8bit, TranscodeOptions},
+};
+
+/// How different a pixel can be from the margin color and still be trimmed
+const TRIM_TOLERANCE: u8 = 24;
+
+/// The screen of an e-reader
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceProfile {
+    /// The name used in the CLI, e.g. `kindle-paperwhite`
+    pub name: &'static str,
+    /// The screen width in portrait
+    pub width: u32,
+    /// The screen height in portrait
+    pub height: u32,
+    /// Is it a color screen
+    pub color: bool,
+    /// The default gamma for the screen
+    pub gamma: f32,
+}
+
+/// All the known devices
+pub const DEVICE_PROFILES: [DeviceProfile; 10] = [
+    DeviceProfile::eink("kindle", 1072, 1448),
+    DeviceProfile::eink("kindle-paperwhite", 1236, 1648),
+    DeviceProfile::eink("kindle-oasis", 1264, 1680),
+    DeviceProfile::eink("kindle-scribe", 1860, 2480),
+    DeviceProfile::color("kindle-colorsoft", 1264, 1680),
+    DeviceProfile::eink("kobo-clara", 1072, 1448),
+    DeviceProfile::color("kobo-clara-colour", 1072, 1448),
+    DeviceProfile::eink("kobo-libra", 1264, 1680),
+    DeviceProfile::eink("kobo-sage", 1440, 1920),
+    DeviceProfile::eink("kobo-elipsa", 1404, 1872),
+];
+
+impl DeviceProfile {
+    const fn eink(name: &'static str, width: u32, height: u32) -> Self {
+        Self {
+            name,
+            width,
+            height,
+            color: false,
+            gamma: 1.8,
+        }
+    }
+
+    const fn color(name: &'static str, width: u32, height: u32) -> Self {
+        Self {
+            name,
+            width,
+            height,
+            color: true,
+            gamma: 1.4,
+        }
+    }
+}
+
+/// Find the device profile by the name
+pub fn find_profile(name: &str) -> Option<&'static DeviceProfile> {
+    DEVICE_PROFILES
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// The order of the halves when splitting a spread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitOrder {
+    /// The right half comes first, the usual order for manga
+    RightToLeft,
+    /// The left half comes first
+    LeftToRight,
+}
+
+/// The options for [`process_page`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceOptions {
+    /// Fit the page inside `(width, height)`, smaller pages are not enlarged
+    pub size: Option<(u32, u32)>,
+    /// Trim the uniform margins around the page
+    pub trim: bool,
+    /// Split landscape pages into two
+    pub split: Option<SplitOrder>,
+    /// Values above `1.0` darken the midtones, `1.0` keeps the page as is
+    pub gamma: f32,
+    /// The contrast adjustment in percent, negative values reduce the contrast
+    pub contrast: f32,
+    /// Convert the page to grayscale
+    pub grayscale: bool,
+}
+
+impl Default for DeviceOptions {
+    fn default() -> Self {
+        Self {
+            size: None,
+            trim: false,
+            split: None,
+            gamma: 1.0,
+            contrast: 0.0,
+            grayscale: false,
+        }
+    }
+}
+
+impl DeviceOptions {
+    /// Create the options for the device, the margins are not trimmed and spreads are not split
+    pub fn from_profile(profile: &DeviceProfile) -> Self {
+        Self {
+            size: Some((profile.width, profile.height)),
+            trim: false,
+            split: None,
+            gamma: profile.gamma,
+            contrast: 0.0,
+            grayscale: !profile.color,
+        }
+    }
+}
+
+/// Process a single page, returns two pages in reading order if it is split
+pub fn process_page(image: DynamicImage, options: &DeviceOptions) -> Vec<DynamicImage> {
+    let image = if options.grayscale {
+        image.grayscale()
+    } else {
+        image
+    };
+    let image = if options.trim {
+        trim_margins(image)
+    } else {
+        image
+    };
+
+    let pages = match options.split {
+        Some(order) if image.width() > image.height() => split_spread(&image, order).to_vec(),
+        _ => vec![image],
+    };
+
+    pages
+        .into_iter()
+        .map(|page| {
+            let page = match options.size {
+                Some((width, height)) if page.width() > width || page.height() > height => {
+                    page.resize(width, height, FilterType::Lanczos3)
+                }
+                _ => page,
+            };
+            let page = if options.gamma != 1.0 {
+                adjust_gamma(page, options.gamma)
+            } else {
+                page
+            };
+
+            if options.contrast != 0.0 {
+                page.adjust_contrast(options.contrast)
+            } else {
+                page
+            }
+        })
+        .collect()
+}
+
+/// Decode the decrypted page, process it, and encode each resulting page
+///
+/// Returns the encoded data and the extension without the dot for each page.
+pub fn process_image(
+    data: &[u8],
+    options: &DeviceOptions,
+    transcode: &TranscodeOptions,
+) -> Result<Vec<(Vec<u8>, &'static str)>, ImageError> {
+    let source = image::guess_format(data)?;
+    let image = image::load_from_memory_with_format(data, source)?;
+
+    process_page(image, options)
+        .into_iter()
+        .map(|page| encode(page, source, transcode))
+        .collect()
+}
+
+/// Crop the rows and columns on the edges that have the same color as the top-left corner
+fn trim_margins(image: DynamicImage) -> DynamicImage {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+    if width == 0 || height == 0 {
+        return image;
+    }
+    let background = luma.get_pixel(0, 0)[0];
+    let is_margin = |x: u32, y: u32| luma.get_pixel(x, y)[0].abs_diff(background) <= TRIM_TOLERANCE;
+
+    let is_blank_row = |y: u32| (0..width).all(|x| is_margin(x, y));
+    let top = match (0..height).find(|&y| !is_blank_row(y)) {
+        Some(top) => top,
+        // The whole page is blank
+        None => return image,
+    };
+    // There is at least one non-blank row
+    let bottom = (0..height).rev().find(|&y| !is_blank_row(y)).unwrap() + 1;
+
+    let is_blank_column = |x: u32| (top..bottom).all(|y| is_margin(x, y));
+    let left = (0..width).find(|&x| !is_blank_column(x)).unwrap();
+    let right = (0..width).rev().find(|&x| !is_blank_column(x)).unwrap() + 1;
+
+    if (left, top, right, bottom) == (0, 0, width, height) {
+        return image;
+    }
+
+    image.crop_imm(left, top, right - left, bottom - top)
+}
+
+/// Split the spread in the middle, in reading order
+fn split_spread(image: &DynamicImage, order: SplitOrder) -> [DynamicImage; 2] {
+    let half = image.width() / 2;
+    let left = image.crop_imm(0, 0, half, image.height());
+    let right = image.crop_imm(half, 0, image.width() - half, image.height());
+
+    match order {
+        SplitOrder::RightToLeft => [right, left],
+        SplitOrder::LeftToRight => [left, right],
+    }
+}
+
+fn adjust_gamma(image: DynamicImage, gamma: f32) -> DynamicImage {
+    let lut: [u8; 256] = std::array::from_fn(|v| {
+        ((v as f32 / 255.0).powf(gamma) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    });
+
+    match to_8bit(image) {
+        DynamicImage::ImageLuma8(mut image) => {
+            apply_lut(&mut image, &lut);
+            DynamicImage::ImageLuma8(image)
+        }
+        DynamicImage::ImageLumaA8(mut image) => {
+            apply_lut(&mut image, &lut);
+            DynamicImage::ImageLumaA8(image)
+        }
+        DynamicImage::ImageRgb8(mut image) => {
+            apply_lut(&mut image, &lut);
+            DynamicImage::ImageRgb8(image)
+        }
+        DynamicImage::ImageRgba8(mut image) => {
+            apply_lut(&mut image, &lut);
+            DynamicImage::ImageRgba8(image)
+        }
+        // `to_8bit` only returns the variants above
+        image => image,
+    }
+}
+
+fn apply_lut<P: Pixel<Subpixel = u8>>(image: &mut ImageBuffer<P, Vec<u8>>, lut: &[u8; 256]) {
+    for pixel in image.pixels_mut() {
+        pixel.apply_without_alpha(|v| lut[v as usize]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::manifest::{ManifestImage, VolumeManifest};
+
+use super::{comicinfo::ComicInfo, image_filename, ExportError};
+
+fn write_archive(
+    directory: &Path,
+    images: &[ManifestImage],
+    info: ComicInfo,
+    target: &Path,
+) -> Result<(), ExportError> {
+    let file = std::fs::File::create(target)?;
+    let mut archive = ZipWriter::new(std::io::BufWriter::new(file));
+    // Images are already compressed, so we just store them.
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let info = info.with_pages(images.iter().map(|i| i.page.episode.is_none()).collect());
+    archive.start_file("ComicInfo.xml", options)?;
+    archive.write_all(info.to_xml().as_bytes())?;
+
+    for image in images {
+        let data = std::fs::read(directory.join(image.filename))?;
+        archive.start_file(image_filename(image), options)?;
+        archive.write_all(&data)?;
+    }
+
+    archive.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Package the whole downloaded volume into a single CBZ archive
+pub fn export_cbz(
+    directory: &Path,
+    manifest: &VolumeManifest,
+    info: &ComicInfo,
+    target: &Path,
+) -> Result<(), ExportError> {
+    if !manifest.is_complete(directory) {
+        return Err(ExportError::Incomplete);
+    }
+    if manifest.is_encrypted() {
+        return Err(ExportError::Encrypted);
+    }
+
+    write_archive(directory, &manifest.images(), info.clone(), target)
+}
+
+/// Package each episode of the downloaded volume into its own CBZ archive
+///
+/// The archives are named `<prefix>_eNNN.cbz` inside the target directory,
+/// the cover is not included in the episode archives.
+///
+/// Returns all the created archives.
+pub fn export_cbz_episodes(
+    directory: &Path,
+    manifest: &VolumeManifest,
+    info: &ComicInfo,
+    target_dir: &Path,
+    prefix: &str,
+) -> Result<Vec<PathBuf>, ExportError> {
+    if !manifest.is_complete(directory) {
+        return Err(ExportError::Incomplete);
+    }
+    if manifest.is_encrypted() {
+        return Err(ExportError::Encrypted);
+    }
+
+    let mut episodes: Vec<i32> = manifest.pages.iter().filter_map(|p| p.episode).collect();
+    episodes.dedup();
+
+    let mut archives = vec![];
+    for episode in episodes {
+        let images: Vec<ManifestImage> = manifest
+            .images()
+            .into_iter()
+            .filter(|i| i.page.episode == Some(episode))
+            .collect();
+
+        let target = target_dir.join(format!("{}_e{:03}.cbz", prefix, episode));
+        write_archive(
+            directory,
+            &images,
+            info.clone().with_episode(episode),
+            &target,
+        )?;
+        archives.push(target);
+    }
+
+    Ok(archives)
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use crate::{
+    client::BASE_HOST,
+    models::{Comic, Volume},
+};
+
+use super::{escape_xml, parse_release_date};
+
+/// A minimal `ComicInfo.xml` representation
+///
+/// Ref: <https://anansi-project.github.io/docs/comicinfo/schemas/v2.0>
+#[derive(Debug, Clone, Default)]
+pub struct ComicInfo {
+    pub title: String,
+    pub series: String,
+    pub number: String,
+    pub volume: i32,
+    pub count: Option<usize>,
+    pub writer: Vec<String>,
+    pub genre: Vec<String>,
+    pub web: Option<String>,
+    pub release_date: Option<time::Date>,
+    /// Page indexes in the archive, the first one with `true` is the cover
+    pub pages: Vec<bool>,
+}
+
+impl ComicInfo {
+    /// Create a new `ComicInfo` for the whole volume
+    pub fn new(comic: &Comic, volume: &Volume) -> Self {
+        Self {
+            title: volume.name.clone(),
+            series: comic.title.clone(),
+            number: volume.number.to_string(),
+            volume: volume.number,
+            count: None,
+            writer: comic.metadata.creators.clone(),
+            genre: comic.genres.iter().map(|g| g.name.clone()).collect(),
+            web: Some(format!("https://{}/{}", &*BASE_HOST, comic.slug)),
+            release_date: parse_release_date(volume.release_at.as_deref()),
+            pages: vec![],
+        }
+    }
+
+    /// Mark this as a single episode of the volume
+    pub fn with_episode(mut self, episode: i32) -> Self {
+        self.title = format!("{} - Chapter {}", self.title, episode);
+        self.number = episode.to_string();
+        self
+    }
+
+    /// Set the total volume count, this will mark the series as completed in most readers
+    pub fn with_count(mut self, count: Option<usize>) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Set the pages, `true` marks the page as the front cover
+    pub fn with_pages(mut self, pages: Vec<bool>) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// Serialize into the XML string
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n");
+
+        let mut push_tag = |tag: &str, value: &str| {
+            if !value.is_empty() {
+                writeln!(xml, "  <{tag}>{}</{tag}>", escape_xml(value)).unwrap();
+            }
+        };
+
+        push_tag("Title", &self.title);
+        push_tag("Series", &self.series);
+        push_tag("Number", &self.number);
+        if let Some(count) = self.count {
+            push_tag("Count", &count.to_string());
+        }
+        push_tag("Volume", &self.volume.to_string());
+        if let Some(date) = self.release_date {
+            push_tag("Year", &date.year().to_string());
+            push_tag("Month", &(date.month() as u8).to_string());
+            push_tag("Day", &date.day().to_string());
+        }
+        push_tag("Writer", &self.writer.join(", "));
+        push_tag("Genre", &self.genre.join(", "));
+        if let Some(web) = &self.web {
+            push_tag("Web", web);
+        }
+        push_tag("PageCount", &self.pages.len().to_string());
+        push_tag("Manga", "YesAndRightToLeft");
+
+        if !self.pages.is_empty() {
+            xml.push_str("  <Pages>\n");
+            for (idx, is_cover) in self.pages.iter().enumerate() {
+                if *is_cover {
+                    writeln!(xml, "    <Page Image=\"{}\" Type=\"FrontCover\" />", idx).unwrap();
+                } else {
+                    writeln!(xml, "    <Page Image=\"{}\" />", idx).unwrap();
+                }
+            }
+            xml.push_str("  </Pages>\n");
+        }
+
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+}
@@ -0,0 +1,332 @@
+use std::{fmt::Write as _, io::Write, path::Path};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::manifest::{ManifestPage, VolumeManifest};
+
+use super::{escape_xml, image_filename, image_name, parse_release_date, ExportError};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A single page in the EPUB
+struct EpubPage<'a> {
+    page: &'a ManifestPage,
+    /// The file in the volume directory
+    source: &'a str,
+    /// The file in the archive, named by the page index like the CBZ
+    filename: String,
+    id: String,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+fn guess_media_type(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+fn make_page_xhtml(title: &str, page: &EpubPage) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body>
+  <img src="../images/{filename}" alt="{id}"/>
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+        width = page.width,
+        height = page.height,
+        filename = page.filename,
+        id = page.id,
+    )
+}
+
+fn make_nav_xhtml(title: &str, pages: &[&EpubPage]) -> String {
+    let mut toc = String::new();
+    for page in pages {
+        let label = match page.page.episode {
+            None => "Cover".to_string(),
+            Some(episode) => format!("Chapter {}", episode),
+        };
+
+        writeln!(
+            toc,
+            "      <li><a href=\"pages/{}.xhtml\">{}</a></li>",
+            page.id,
+            escape_xml(&label)
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{toc}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+        toc = toc,
+    )
+}
+
+/// Build a fixed-layout EPUB 3 from the downloaded volume
+///
+/// The table of contents is built from the episode boundaries of the pages,
+/// the page without an episode is used as the cover.
+///
+/// This requires the comic and volume metadata to be recorded in the manifest.
+pub fn export_epub(
+    directory: &Path,
+    manifest: &VolumeManifest,
+    target: &Path,
+) -> Result<(), ExportError> {
+    if !manifest.is_complete(directory) {
+        return Err(ExportError::Incomplete);
+    }
+    if manifest.is_encrypted() {
+        return Err(ExportError::Encrypted);
+    }
+
+    let (comic, volume) = match (&manifest.comic, &manifest.volume_info) {
+        (Some(comic), Some(volume)) => (comic, volume),
+        _ => return Err(ExportError::MissingMetadata),
+    };
+
+    let title = format!("{} - {}", comic.title, volume.name);
+
+    let mut pages = vec![];
+    for image in manifest.images() {
+        let (width, height) = image::image_dimensions(directory.join(image.filename))?;
+        pages.push(EpubPage {
+            page: image.page,
+            source: image.filename,
+            filename: image_filename(&image),
+            id: image_name(&image),
+            media_type: guess_media_type(image.filename),
+            width,
+            height,
+        });
+    }
+
+    // Only the first page of each episode goes into the TOC
+    let mut toc_pages: Vec<&EpubPage> = vec![];
+    for page in pages.iter() {
+        let is_new = toc_pages
+            .last()
+            .map(|last| last.page.episode != page.page.episode)
+            .unwrap_or(true);
+        if is_new {
+            toc_pages.push(page);
+        }
+    }
+
+    let cover = pages.iter().find(|p| p.page.episode.is_none());
+    // The cover size often differs from the pages, prefer the first actual page
+    let resolution = pages
+        .iter()
+        .find(|p| p.page.episode.is_some())
+        .or(cover)
+        .map(|p| (p.width, p.height));
+
+    let modified = time::OffsetDateTime::now_utc();
+    let mut opf = String::new();
+    writeln!(opf, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        opf,
+        r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="bookid" prefix="rendition: http://www.idpf.org/vocab/rendition/#">"#
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        r#"  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">"#
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <dc:identifier id=\"bookid\">urn:emancipated:{}:{}</dc:identifier>",
+        escape_xml(&comic.slug),
+        escape_xml(&volume.slug)
+    )
+    .unwrap();
+    writeln!(opf, "    <dc:title>{}</dc:title>", escape_xml(&title)).unwrap();
+    for creator in comic.metadata.creators.iter() {
+        writeln!(opf, "    <dc:creator>{}</dc:creator>", escape_xml(creator)).unwrap();
+    }
+    for genre in comic.genres.iter() {
+        writeln!(
+            opf,
+            "    <dc:subject>{}</dc:subject>",
+            escape_xml(&genre.name)
+        )
+        .unwrap();
+    }
+    if let Some(date) = parse_release_date(volume.release_at.as_deref()) {
+        writeln!(
+            opf,
+            "    <dc:date>{:04}-{:02}-{:02}</dc:date>",
+            date.year(),
+            date.month() as u8,
+            date.day()
+        )
+        .unwrap();
+    }
+    writeln!(
+        opf,
+        "    <meta property=\"dcterms:modified\">{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z</meta>",
+        modified.year(),
+        modified.month() as u8,
+        modified.day(),
+        modified.hour(),
+        modified.minute(),
+        modified.second()
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>",
+        escape_xml(&comic.title)
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta refines=\"#series\" property=\"collection-type\">series</meta>"
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta refines=\"#series\" property=\"group-position\">{}</meta>",
+        volume.number
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta property=\"rendition:layout\">pre-paginated</meta>"
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta property=\"rendition:orientation\">auto</meta>"
+    )
+    .unwrap();
+    writeln!(
+        opf,
+        "    <meta property=\"rendition:spread\">landscape</meta>"
+    )
+    .unwrap();
+    if let Some(cover) = cover {
+        writeln!(
+            opf,
+            "    <meta name=\"cover\" content=\"img_{}\"/>",
+            cover.id
+        )
+        .unwrap();
+    }
+    if let Some((width, height)) = resolution {
+        writeln!(
+            opf,
+            "    <meta name=\"original-resolution\" content=\"{}x{}\"/>",
+            width, height
+        )
+        .unwrap();
+    }
+    writeln!(opf, "  </metadata>").unwrap();
+
+    writeln!(opf, "  <manifest>").unwrap();
+    writeln!(
+        opf,
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>"
+    )
+    .unwrap();
+    for page in pages.iter() {
+        let properties = if cover.is_some_and(|c| c.id == page.id) {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        writeln!(
+            opf,
+            "    <item id=\"img_{}\" href=\"images/{}\" media-type=\"{}\"{}/>",
+            page.id, page.filename, page.media_type, properties
+        )
+        .unwrap();
+        writeln!(
+            opf,
+            "    <item id=\"{id}\" href=\"pages/{id}.xhtml\" media-type=\"application/xhtml+xml\"/>",
+            id = page.id
+        )
+        .unwrap();
+    }
+    writeln!(opf, "  </manifest>").unwrap();
+
+    // Manga are read right-to-left
+    writeln!(opf, "  <spine page-progression-direction=\"rtl\">").unwrap();
+    for page in pages.iter() {
+        writeln!(opf, "    <itemref idref=\"{}\"/>", page.id).unwrap();
+    }
+    writeln!(opf, "  </spine>").unwrap();
+    writeln!(opf, "</package>").unwrap();
+
+    let file = std::fs::File::create(target)?;
+    let mut archive = ZipWriter::new(std::io::BufWriter::new(file));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // The mimetype must be the first file and uncompressed
+    archive.start_file("mimetype", options)?;
+    archive.write_all(b"application/epub+zip")?;
+
+    archive.start_file("META-INF/container.xml", options)?;
+    archive.write_all(CONTAINER_XML.as_bytes())?;
+
+    archive.start_file("OEBPS/content.opf", options)?;
+    archive.write_all(opf.as_bytes())?;
+
+    archive.start_file("OEBPS/nav.xhtml", options)?;
+    archive.write_all(make_nav_xhtml(&title, &toc_pages).as_bytes())?;
+
+    for page in pages.iter() {
+        let data = std::fs::read(directory.join(page.source))?;
+        archive.start_file(format!("OEBPS/images/{}", page.filename), options)?;
+        archive.write_all(&data)?;
+
+        archive.start_file(format!("OEBPS/pages/{}.xhtml", page.id), options)?;
+        archive.write_all(make_page_xhtml(&title, page).as_bytes())?;
+    }
+
+    archive.finish()?.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Export downloaded volumes into other formats.
+//!
+//! All the exporters work from a downloaded volume directory and its [`VolumeManifest`].
+//!
+//! [`VolumeManifest`]: crate::manifest::VolumeManifest
+
+pub mod cbz;
+pub mod comicinfo;
+pub mod epub;
+pub mod pdf;
+
+use crate::manifest::ManifestImage;
+
+/// Error that happens when exporting a volume
+pub enum ExportError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Image(image::ImageError),
+    Pdf(lopdf::Error),
+    /// The volume is not fully downloaded yet
+    Incomplete,
+    /// The pages are kept encrypted, decrypt them first
+    Encrypted,
+    /// The manifest does not have the comic or volume metadata
+    MissingMetadata,
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ExportError {
+    fn from(e: zip::result::ZipError) -> Self {
+        Self::Zip(e)
+    }
+}
+
+impl From<image::ImageError> for ExportError {
+    fn from(e: image::ImageError) -> Self {
+        Self::Image(e)
+    }
+}
+
+impl From<lopdf::Error> for ExportError {
+    fn from(e: lopdf::Error) -> Self {
+        Self::Pdf(e)
+    }
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Zip(e) => write!(f, "Zip Error: {}", e),
+            Self::Image(e) => write!(f, "Image Error: {}", e),
+            Self::Pdf(e) => write!(f, "PDF Error: {}", e),
+            Self::Incomplete => write!(f, "Volume is not fully downloaded"),
+            Self::Encrypted => write!(f, "Volume is still encrypted, decrypt it first"),
+            Self::MissingMetadata => write!(f, "Missing comic or volume metadata in the manifest"),
+        }
+    }
+}
+
+impl std::fmt::Debug for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Zip(e) => write!(f, "Zip Error: {}", e),
+            Self::Image(e) => write!(f, "Image Error: {}", e),
+            Self::Pdf(e) => write!(f, "PDF Error: {}", e),
+            Self::Incomplete => write!(f, "Volume is not fully downloaded"),
+            Self::Encrypted => write!(f, "Volume is still encrypted, decrypt it first"),
+            Self::MissingMetadata => write!(f, "Missing comic or volume metadata in the manifest"),
+        }
+    }
+}
+
+/// Escape the special characters for XML
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Name the image by the page index, with the part number for the extra files of the page
+///
+/// The extra files sort right after the main file, e.g. `p005`, `p005_1`, `p006`.
+pub(crate) fn image_name(image: &ManifestImage) -> String {
+    match image.part {
+        0 => format!("p{:03}", image.page.index),
+        part => format!("p{:03}_{}", image.page.index, part),
+    }
+}
+
+/// Name the archive entry by the page index, so readers sort it correctly regardless of the layout
+pub(crate) fn image_filename(image: &ManifestImage) -> String {
+    match std::path::Path::new(image.filename).extension() {
+        Some(ext) => format!("{}.{}", image_name(image), ext.to_string_lossy()),
+        None => image_name(image),
+    }
+}
+
+/// Parse the release date of a volume into a date
+pub(crate) fn parse_release_date(release_at: Option<&str>) -> Option<time::Date> {
+    release_at.and_then(|release_at| {
+        time::OffsetDateTime::parse(release_at, &time::format_description::well_known::Rfc3339)
+            .ok()
+            .map(|dt| dt.date())
+    })
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Bookmark, Document, Object, Stream, StringFormat,
+};
+
+use crate::manifest::VolumeManifest;
+
+use super::{parse_release_date, ExportError};
+
+/// Encode a text string for the PDF, non-ASCII text will be encoded as UTF-16BE
+fn pdf_text(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let mut encoded = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            encoded.extend_from_slice(&unit.to_be_bytes());
+        }
+        Object::String(encoded, StringFormat::Hexadecimal)
+    }
+}
+
+/// Create the image XObject from the image data
+///
+/// JPEG is embedded as is, everything else is decoded and re-compressed losslessly.
+fn make_image_stream(data: &[u8]) -> Result<(Stream, u32, u32), ExportError> {
+    let format = image::guess_format(data)?;
+    let decoded = image::load_from_memory(data)?;
+    let (width, height) = (decoded.width(), decoded.height());
+    let is_gray = !decoded.color().has_color();
+
+    let color_space = if is_gray { "DeviceGray" } else { "DeviceRGB" };
+    let mut info = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width,
+        "Height" => height,
+        "ColorSpace" => color_space,
+        "BitsPerComponent" => 8,
+    };
+
+    let stream = if format == image::ImageFormat::Jpeg {
+        info.set("Filter", "DCTDecode");
+        Stream::new(info, data.to_vec())
+    } else {
+        let pixels = if is_gray {
+            decoded.into_luma8().into_raw()
+        } else {
+            decoded.into_rgb8().into_raw()
+        };
+        let mut stream = Stream::new(info, pixels);
+        stream.compress()?;
+        stream
+    };
+
+    Ok((stream, width, height))
+}
+
+/// Assemble all the pages of the downloaded volume into a single PDF
+///
+/// Each page is one image at native resolution, with a bookmark for each episode.
+///
+/// The document information is filled from the comic and volume metadata in the manifest if available.
+pub fn export_pdf(
+    directory: &Path,
+    manifest: &VolumeManifest,
+    target: &Path,
+) -> Result<(), ExportError> {
+    if !manifest.is_complete(directory) {
+        return Err(ExportError::Incomplete);
+    }
+    if manifest.is_encrypted() {
+        return Err(ExportError::Encrypted);
+    }
+
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+
+    let mut page_ids = vec![];
+    let mut last_episode: Option<Option<i32>> = None;
+    for image in manifest.images() {
+        let page = image.page;
+        let data = std::fs::read(directory.join(image.filename))?;
+        let (image_stream, width, height) = make_image_stream(&data)?;
+        let image_id = doc.add_object(image_stream);
+
+        let content = Content {
+            operations: vec![
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        width.into(),
+                        0.into(),
+                        0.into(),
+                        height.into(),
+                        0.into(),
+                        0.into(),
+                    ],
+                ),
+                Operation::new("Do", vec!["Im0".into()]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! {
+                "XObject" => dictionary! {
+                    "Im0" => image_id,
+                },
+            },
+        });
+        page_ids.push(page_id.into());
+
+        // Add a bookmark on each episode boundary
+        if last_episode != Some(page.episode) {
+            let title = match page.episode {
+                None => "Cover".to_string(),
+                Some(episode) => format!("Chapter {}", episode),
+            };
+            doc.add_bookmark(Bookmark::new(title, [0.0, 0.0, 0.0], 0, page_id), None);
+            last_episode = Some(page.episode);
+        }
+    }
+
+    let page_count = page_ids.len() as i64;
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => page_ids,
+            "Count" => page_count,
+        }),
+    );
+
+    let mut catalog = dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "PageLayout" => "SinglePage",
+        "ViewerPreferences" => dictionary! {
+            "Direction" => "R2L",
+        },
+    };
+    if let Some(outline_id) = doc.build_outline() {
+        catalog.set("Outlines", outline_id);
+        catalog.set("PageMode", "UseOutlines");
+    }
+    let catalog_id = doc.add_object(catalog);
+    doc.trailer.set("Root", catalog_id);
+
+    let mut info = dictionary! {
+        "Producer" => pdf_text(concat!("emancipated v", env!("CARGO_PKG_VERSION"))),
+    };
+    if let (Some(comic), Some(volume)) = (&manifest.comic, &manifest.volume_info) {
+        info.set(
+            "Title",
+            pdf_text(&format!("{} - {}", comic.title, volume.name)),
+        );
+        info.set("Author", pdf_text(&comic.metadata.creators.join(", ")));
+        info.set("Subject", pdf_text(&comic.title));
+        let keywords = comic
+            .genres
+            .iter()
+            .map(|g| g.name.clone())
+            .collect::<Vec<String>>()
+            .join(", ");
+        info.set("Keywords", pdf_text(&keywords));
+        if let Some(date) = parse_release_date(volume.release_at.as_deref()) {
+            info.set(
+                "CreationDate",
+                Object::string_literal(format!(
+                    "D:{:04}{:02}{:02}",
+                    date.year(),
+                    date.month() as u8,
+                    date.day()
+                )),
+            );
+        }
+    }
+    let info_id = doc.add_object(info);
+    doc.trailer.set("Info", info_id);
+
+    doc.compress();
+    doc.save(target)?;
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::io::Write;
 
 use aes::{
-    cipher::{consts::U16, BlockDecryptMut, KeyIvInit},
+    cipher::{consts::U16, BlockDecryptMut, InvalidLength, KeyIvInit},
     Aes256,
 };
 use aes_gcm::{aead::Aead, AesGcm, KeyInit};
@@ -44,41 +44,82 @@ impl std::fmt::Debug for ImageError {
     }
 }
 
-fn decrypt_data(image: &[u8], aes_key: &[u8]) -> Result<Vec<u8>, ImageError> {
-    if image[0] == 2 {
+/// Decrypt the image data with the provided AES key
+///
+/// The encryption mode is determined from the first byte, `2` is AES-GCM and anything else is AES-CBC.
+pub fn decrypt_data(image: &[u8], aes_key: &[u8]) -> Result<Vec<u8>, ImageError> {
+    if image.first() == Some(&2) {
         decrypt_with_aes_gcm(image, aes_key)
     } else {
         decrypt_with_aes_cbc(image, aes_key)
     }
 }
 
-pub(crate) fn load_and_save_image(
-    image: &[u8],
-    aes_key: &[u8],
-    target_dir: &std::path::Path,
-) -> Result<(), ImageError> {
+/// Decrypt the image and verify it can be loaded
+///
+/// Returns the decrypted data and the extension guessed from it, without the dot.
+pub fn decrypt_image(image: &[u8], aes_key: &[u8]) -> Result<(Vec<u8>, &'static str), ImageError> {
     let decrypted = decrypt_data(image, aes_key)?;
     let extension = image::guess_format(&decrypted).unwrap_or(image::ImageFormat::Png);
 
     // Try loading the image
     image::load_from_memory(&decrypted)?;
 
+    Ok((decrypted, extension.extensions_str()[0]))
+}
+
+/// Use the image as is if it is not encrypted, otherwise decrypt it
+///
+/// This is used for the cover, which might be served unencrypted.
+pub fn decode_image(image: &[u8], aes_key: &[u8]) -> Result<(Vec<u8>, &'static str), ImageError> {
+    if let Ok(format) = image::guess_format(image) {
+        if image::load_from_memory_with_format(image, format).is_ok() {
+            return Ok((image.to_vec(), format.extensions_str()[0]));
+        }
+    }
+
+    decrypt_image(image, aes_key)
+}
+
+/// Read the width and height of the encoded image from its header, without decoding it
+pub fn image_dimensions(data: &[u8]) -> Result<(u32, u32), ImageError> {
+    let reader = image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+
+    Ok(reader.into_dimensions()?)
+}
+
+/// Decrypt the image, verify it and save it to the target path
+///
+/// The target path should not contain any extension, it will be guessed from the decrypted data.
+///
+/// Returns the final path of the saved image.
+pub fn load_and_save_image(
+    image: &[u8],
+    aes_key: &[u8],
+    target_dir: &std::path::Path,
+) -> Result<std::path::PathBuf, ImageError> {
+    let (decrypted, ext_str) = decrypt_image(image, aes_key)?;
+
     // Save the image
     // Path is already name but without extension, so we add the extension
-    let ext_str = extension.extensions_str()[0];
     let path = target_dir.with_extension(ext_str);
 
     // Open the file and write the image
     let file = std::fs::File::create(&path)?;
     let mut writer = std::io::BufWriter::new(file);
     writer.write_all(&decrypted)?;
+    writer.flush()?;
 
-    Ok(())
+    Ok(path)
 }
 
 fn decrypt_with_aes_gcm(image: &[u8], aes_key: &[u8]) -> Result<Vec<u8>, ImageError> {
     let key = aes_gcm::Key::<Aes256Gcm16>::from_slice(aes_key);
-    let cipher = Aes256Gcm16::new(&key);
+    let cipher = Aes256Gcm16::new(key);
+
+    if image.len() < 18 {
+        return Err(ImageError::AESLength(InvalidLength));
+    }
 
     let nonce = &image[2..18];
     let ciphertext = &image[18..];
@@ -88,6 +129,10 @@ fn decrypt_with_aes_gcm(image: &[u8], aes_key: &[u8]) -> Result<Vec<u8>, ImageEr
 }
 
 fn decrypt_with_aes_cbc(image: &[u8], aes_key: &[u8]) -> Result<Vec<u8>, ImageError> {
+    if image.len() < 16 {
+        return Err(ImageError::AESLength(InvalidLength));
+    }
+
     let iv = &image[0..16];
     let mut ciphertext = image[16..].to_vec();
     let cipher = PKCS7128CbcDec::new(aes_key.into(), iv.into());
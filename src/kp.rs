@@ -1,16 +1,20 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use base64::{engine::general_purpose, Engine as _};
 use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, EncodePublicKey};
 use rsa::sha2::Sha256;
 use rsa::{pkcs8::DecodePublicKey, RsaPrivateKey, RsaPublicKey};
 
-pub(crate) enum RSAError {
+use crate::config::write_private_file;
+
+pub enum RSAError {
     Io(std::io::Error),
     RSA(rsa::Error),
     PKCS8(rsa::pkcs8::Error),
     SpkiPKCS8(rsa::pkcs8::spki::Error),
     B64Decode(base64::DecodeError),
+    /// The private key is encrypted and no passphrase is provided
+    MissingPassphrase,
 }
 
 impl From<std::io::Error> for RSAError {
@@ -51,6 +55,9 @@ impl std::fmt::Display for RSAError {
             RSAError::PKCS8(e) => write!(f, "PKCS8 Error: {}", e),
             RSAError::SpkiPKCS8(e) => write!(f, "SPKI PKCS8 Error: {}", e),
             RSAError::B64Decode(e) => write!(f, "Base64 Decode Error: {}", e),
+            RSAError::MissingPassphrase => {
+                write!(f, "Private key is encrypted, passphrase required")
+            }
         }
     }
 }
@@ -63,11 +70,14 @@ impl std::fmt::Debug for RSAError {
             RSAError::PKCS8(e) => write!(f, "PKCS8 Error: {}", e),
             RSAError::SpkiPKCS8(e) => write!(f, "SPKI PKCS8 Error: {}", e),
             RSAError::B64Decode(e) => write!(f, "Base64 Decode Error: {}", e),
+            RSAError::MissingPassphrase => {
+                write!(f, "Private key is encrypted, passphrase required")
+            }
         }
     }
 }
 
-pub(crate) fn generate_key_pair() -> Result<(RsaPrivateKey, RsaPublicKey), RSAError> {
+pub fn generate_key_pair() -> Result<(RsaPrivateKey, RsaPublicKey), RSAError> {
     // Generate Key-Pair of 2048 bits with 65537 exponent
     let mut rng = rand::thread_rng();
     let priv_key = RsaPrivateKey::new(&mut rng, 2048)?;
@@ -76,37 +86,68 @@ pub(crate) fn generate_key_pair() -> Result<(RsaPrivateKey, RsaPublicKey), RSAEr
     Ok((priv_key, pub_key))
 }
 
-pub(crate) fn load_key_pair(
+/// Get the paths of the key pair of the account in the directory, the private key first
+pub fn key_pair_paths(directory: &Path, email: &str) -> (PathBuf, PathBuf) {
+    let email_64 = hash_b64(email);
+
+    (
+        directory.join(format!("{}_kp.pem", email_64)),
+        directory.join(format!("{}_kp.pub", email_64)),
+    )
+}
+
+/// Load the key pair from the PEM files
+///
+/// The passphrase is required if the private key is an encrypted PKCS#8 PEM.
+pub fn load_key_pair(
     private_key: &Path,
     public_key: &Path,
+    passphrase: Option<&str>,
 ) -> Result<(RsaPrivateKey, RsaPublicKey), RSAError> {
-    let read_pk = RsaPrivateKey::read_pkcs8_pem_file(private_key)?;
+    let private_pem = std::fs::read_to_string(private_key)?;
+    let read_pk = if private_pem.contains("BEGIN ENCRYPTED PRIVATE KEY") {
+        let passphrase = passphrase.ok_or(RSAError::MissingPassphrase)?;
+        RsaPrivateKey::from_pkcs8_encrypted_pem(&private_pem, passphrase)?
+    } else {
+        RsaPrivateKey::from_pkcs8_pem(&private_pem)?
+    };
     let read_pub = RsaPublicKey::read_public_key_pem_file(public_key)?;
 
     Ok((read_pk, read_pub))
 }
 
-pub(crate) fn write_key_pair(
+/// Write the key pair as PEM files
+///
+/// The private key is written as an encrypted PKCS#8 PEM if a passphrase is provided,
+/// and is only readable by the current user on Unix.
+pub fn write_key_pair(
     private_key: &Path,
     public_key: &Path,
     priv_key: &RsaPrivateKey,
     pub_key: &RsaPublicKey,
+    passphrase: Option<&str>,
 ) -> Result<(), RSAError> {
     #[cfg(target_os = "windows")]
     let line_ending = rsa::pkcs8::LineEnding::CRLF;
     #[cfg(not(target_os = "windows"))]
     let line_ending = rsa::pkcs8::LineEnding::LF;
 
-    let priv_key_pkcs8 = priv_key.to_pkcs8_pem(line_ending)?;
+    let priv_key_pkcs8 = match passphrase {
+        Some(passphrase) => {
+            let mut rng = rand::thread_rng();
+            priv_key.to_pkcs8_encrypted_pem(&mut rng, passphrase, line_ending)?
+        }
+        None => priv_key.to_pkcs8_pem(line_ending)?,
+    };
     let pub_key_pkcs1 = pub_key.to_public_key_pem(line_ending)?;
 
-    std::fs::write(private_key, priv_key_pkcs8)?;
+    write_private_file(private_key, priv_key_pkcs8.as_bytes())?;
     std::fs::write(public_key, pub_key_pkcs1)?;
 
     Ok(())
 }
 
-pub(crate) fn create_xhash(public_key: &RsaPublicKey) -> Result<String, RSAError> {
+pub fn create_xhash(public_key: &RsaPublicKey) -> Result<String, RSAError> {
     let spki_der = public_key.to_public_key_der()?;
 
     let spki_b64 = general_purpose::STANDARD.encode(spki_der.as_bytes());
@@ -114,10 +155,7 @@ pub(crate) fn create_xhash(public_key: &RsaPublicKey) -> Result<String, RSAError
     Ok(spki_b64)
 }
 
-pub(crate) fn hash_to_aes_key(
-    private_key: &RsaPrivateKey,
-    hash: &str,
-) -> Result<Vec<u8>, RSAError> {
+pub fn hash_to_aes_key(private_key: &RsaPrivateKey, hash: &str) -> Result<Vec<u8>, RSAError> {
     let hash_bytes = general_purpose::STANDARD.decode(hash)?;
 
     let decryped_key = private_key.decrypt(rsa::Oaep::new::<Sha256>(), &hash_bytes)?;
@@ -125,7 +163,7 @@ pub(crate) fn hash_to_aes_key(
     Ok(decryped_key)
 }
 
-pub(crate) fn hash_b64(data: &str) -> String {
+pub fn hash_b64(data: &str) -> String {
     let data_bytes = data.as_bytes();
 
     general_purpose::STANDARD.encode(data_bytes)
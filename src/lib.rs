@@ -0,0 +1,38 @@
+//! # emancipated
+//!
+//! A certain tools to get something from a specific website to make it free from their platform.
+//!
+//! This is the library part of the project, the CLI is built on top of this.
+//!
+//! ```no_run
+//! use emancipated::{config::get_config, Client};
+//!
+//! # async fn run() -> Result<(), emancipated::ClientError> {
+//! let mut config = get_config("me@example.com", None)?.expect("Account not found");
+//! let mut client = Client::new(&mut config, None, None)?;
+//!
+//! let results = client.search("oshi").await?;
+//! for comic in results {
+//!     println!("{} ({})", comic.title, comic.slug);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod cassette;
+pub mod client;
+pub mod config;
+pub mod decrypt;
+pub mod device;
+pub mod export;
+pub mod image;
+pub mod kp;
+pub mod manifest;
+pub mod models;
+pub mod retry;
+pub mod settings;
+pub mod spread;
+pub mod template;
+pub mod transcode;
+
+pub use client::{Client, ClientEndpoints, ClientError, DetailedSerdeError};
@@ -1,22 +1,37 @@
 use clap::Parser;
 use cli::{EmancipatedCli, ExitCode};
-use client::ClientError;
-use config::{get_user_path, select_single_account};
+use commands::accounts::select_single_account;
+use emancipated::{
+    cassette::Cassette,
+    config::{get_default_account, get_user_path},
+    device::{find_profile, DeviceOptions},
+    retry::RetryPolicy,
+    settings::Settings,
+    template::{Template, DEFAULT_DIRECTORY_TEMPLATE, DEFAULT_FILE_TEMPLATE},
+    transcode::{TranscodeOptions, DEFAULT_QUALITY},
+    Client, ClientError,
+};
 
 mod cli;
-mod client;
 mod commands;
-mod config;
-mod image;
-mod kp;
-mod models;
 mod term;
 mod win_term;
 
 #[tokio::main]
 async fn main() {
     let cli = EmancipatedCli::parse();
-    let exit_code = entrypoint(cli).await.unwrap();
+    let verbose = cli.verbose;
+    let exit_code = match entrypoint(cli).await {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            let t = term::get_console(verbose);
+            t.error(format!(
+                "{}, run `emancipated doctor` to check your accounts",
+                e
+            ));
+            1
+        }
+    };
 
     std::process::exit(exit_code);
 }
@@ -24,27 +39,144 @@ async fn main() {
 async fn entrypoint(cli: EmancipatedCli) -> Result<ExitCode, ClientError> {
     let t = term::get_console(cli.verbose);
 
-    let parsed_proxy = match cli.proxy {
-        Some(proxy) => match reqwest::Proxy::all(proxy) {
-            Ok(proxy) => Some(proxy),
-            Err(e) => {
-                t.warn(&format!("Unable to parse proxy: {}", e));
-                return Ok(1);
-            }
-        },
-        None => None,
+    let user_home = match get_user_path() {
+        Ok(user_home) => user_home,
+        Err(e) => {
+            t.error(format!("Unable to find the user path: {}", e));
+            return Ok(1);
+        }
     };
+    if let Err(e) = std::fs::create_dir_all(&user_home) {
+        t.error(format!(
+            "Unable to create the user path {}: {}",
+            user_home.display(),
+            e
+        ));
+        return Ok(1);
+    }
 
-    let user_home = get_user_path();
-    if !user_home.exists() {
-        std::fs::create_dir_all(&user_home).unwrap();
+    // Run before loading anything, since it is meant to repair them
+    if let cli::EmancipatedCommands::Doctor { fix } = cli.command {
+        return Ok(commands::doctor::doctor(fix, &t));
     }
 
-    let early_exit = match cli.command.clone() {
-        cli::EmancipatedCommands::Auth { email, password } => {
-            Some(commands::accounts::accounts_auth(email, password, parsed_proxy.clone(), &t).await)
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            t.error(format!(
+                "Unable to load settings: {}, run `emancipated doctor` to check it",
+                e
+            ));
+            return Ok(1);
         }
-        cli::EmancipatedCommands::Accounts => Some(commands::accounts::accounts_all(&t).await),
+    };
+
+    let needs_unlock = !matches!(
+        cli.command,
+        cli::EmancipatedCommands::Export { .. } | cli::EmancipatedCommands::Config { .. }
+    );
+    let passphrase = if needs_unlock {
+        match commands::accounts::unlock_accounts(&t) {
+            Ok(passphrase) => passphrase,
+            Err(_) => return Ok(1),
+        }
+    } else {
+        None
+    };
+
+    // The account is not selected yet, so use the preferred one for the settings
+    let account_hint = cli.account.clone().or_else(get_default_account);
+    let values = commands::settings::resolve_settings(&settings, account_hint.as_deref(), &t);
+    let Some(parsed_proxy) = parse_proxy(cli.proxy.as_deref().or(values.proxy.as_deref()), &t)
+    else {
+        return Ok(1);
+    };
+
+    let early_exit = match cli.command.clone() {
+        cli::EmancipatedCommands::Auth { email, password } => Some(
+            commands::accounts::accounts_auth(
+                email,
+                &password,
+                passphrase.as_ref(),
+                parsed_proxy.clone(),
+                &t,
+            )
+            .await,
+        ),
+        cli::EmancipatedCommands::Accounts { action } => match action {
+            None | Some(cli::AccountsCommands::List) => {
+                Some(commands::accounts::accounts_all(passphrase.as_ref(), &t).await)
+            }
+            Some(cli::AccountsCommands::Remove { email, yes }) => {
+                Some(commands::accounts::accounts_remove(&email, yes, &t))
+            }
+            Some(cli::AccountsCommands::Default { email, clear }) => {
+                Some(commands::accounts::accounts_default(
+                    email.as_deref(),
+                    clear,
+                    passphrase.as_ref(),
+                    &t,
+                ))
+            }
+            Some(cli::AccountsCommands::Encrypt) => Some(commands::accounts::accounts_encrypt(
+                passphrase.as_ref(),
+                &t,
+            )),
+            Some(cli::AccountsCommands::Decrypt) => Some(commands::accounts::accounts_decrypt(
+                passphrase.as_ref(),
+                &t,
+            )),
+            Some(cli::AccountsCommands::Relogin { email, password }) => Some(
+                commands::accounts::accounts_relogin(
+                    email.as_deref().or(cli.account.as_deref()),
+                    &password,
+                    passphrase.as_ref(),
+                    parsed_proxy.clone(),
+                    &t,
+                )
+                .await,
+            ),
+        },
+        cli::EmancipatedCommands::Config { action } => Some(match action {
+            cli::ConfigCommands::Show => {
+                commands::settings::settings_show(&settings, cli.account.as_deref(), &t)
+            }
+            cli::ConfigCommands::Set { key, value } => commands::settings::settings_set(
+                settings.clone(),
+                cli.account.as_deref(),
+                &key,
+                &value,
+                &t,
+            ),
+            cli::ConfigCommands::Unset { key } => commands::settings::settings_unset(
+                settings.clone(),
+                cli.account.as_deref(),
+                &key,
+                &t,
+            ),
+        }),
+        cli::EmancipatedCommands::Decrypt {
+            path,
+            format,
+            per_episode,
+        } => Some(commands::decrypt::volume_decrypt(
+            &t,
+            path,
+            format,
+            per_episode,
+            cli.account.as_deref(),
+            passphrase.as_ref(),
+        )),
+        cli::EmancipatedCommands::Export {
+            path,
+            format,
+            per_episode,
+        } => Some(commands::export::volume_export(
+            &t,
+            path,
+            format,
+            per_episode,
+        )),
         _ => None,
     };
 
@@ -52,7 +184,7 @@ async fn entrypoint(cli: EmancipatedCli) -> Result<ExitCode, ClientError> {
         std::process::exit(exit_code);
     }
 
-    let config = select_single_account(cli.account.as_deref(), &t);
+    let config = select_single_account(cli.account.as_deref(), passphrase.as_ref(), &t);
     let mut config = match config {
         Some(config) => config,
         None => {
@@ -61,28 +193,220 @@ async fn entrypoint(cli: EmancipatedCli) -> Result<ExitCode, ClientError> {
         }
     };
 
-    let mut client = client::Client::new(&mut config, parsed_proxy)?;
+    // Resolve again with the actual account, the invalid values were already reported
+    let (values, _) = settings.resolve_lossy(Some(config.email()));
+    let Some(parsed_proxy) = parse_proxy(cli.proxy.as_deref().or(values.proxy.as_deref()), &t)
+    else {
+        return Ok(1);
+    };
+
+    let mut client = match Client::new(&mut config, passphrase, parsed_proxy) {
+        Ok(client) => client,
+        Err(e) => {
+            t.error(format!(
+                "Unable to create the client for {}: {}, run `emancipated doctor` to check it",
+                config.email(),
+                e
+            ));
+            return Ok(1);
+        }
+    };
+
+    let cassette = match (cli.record, cli.replay) {
+        (Some(directory), _) => Some(Cassette::record(directory)),
+        (None, Some(directory)) => Some(Cassette::replay(directory)),
+        (None, None) => None,
+    };
+    if let Some(cassette) = cassette {
+        match cassette {
+            Ok(cassette) => client = client.with_cassette(cassette),
+            Err(e) => {
+                t.warn(format!("Unable to open cassette: {}", e));
+                return Ok(1);
+            }
+        }
+    }
 
     let exit_code = match cli.command {
-        cli::EmancipatedCommands::Auth {
-            email: _,
-            password: _,
-        } => 0,
+        cli::EmancipatedCommands::Auth { .. } => 0,
         cli::EmancipatedCommands::Account => {
             commands::accounts::accounts_info(&mut client, &t).await
         }
-        cli::EmancipatedCommands::Accounts => 0,
+        cli::EmancipatedCommands::Accounts { .. } => 0,
         cli::EmancipatedCommands::Download {
             slug,
             volume,
             parallel,
-        } => commands::download::manga_download(&mut client, &t, slug, volume, parallel).await,
+            jobs,
+            format,
+            per_episode,
+            layout,
+            cover,
+            output,
+            dir_template,
+            file_template,
+            transcode,
+            quality,
+            grayscale,
+            device,
+            trim,
+            split,
+            spreads,
+            gamma,
+            contrast,
+            keep_encrypted,
+            retries,
+            retry_delay,
+        } => {
+            let jobs = match (jobs, parallel) {
+                (Some(jobs), _) => jobs as usize,
+                (None, true) => values.jobs.unwrap_or(cli::DEFAULT_JOBS),
+                (None, false) => values.jobs.unwrap_or(1),
+            };
+            let format = match format {
+                Some(format) => format,
+                None => match values
+                    .format
+                    .as_deref()
+                    .map(commands::settings::parse_format)
+                {
+                    Some(Ok(format)) => format,
+                    Some(Err(e)) => {
+                        t.error(format!("Invalid format setting: {}", e));
+                        return Ok(1);
+                    }
+                    None => cli::DownloadFormat::Folder,
+                },
+            };
+            let layout = match layout {
+                Some(layout) => layout,
+                None => match values
+                    .layout
+                    .as_deref()
+                    .map(commands::settings::parse_layout)
+                {
+                    Some(Ok(layout)) => layout,
+                    Some(Err(e)) => {
+                        t.error(format!("Invalid layout setting: {}", e));
+                        return Ok(1);
+                    }
+                    None if per_episode && format == cli::DownloadFormat::Folder => {
+                        cli::DownloadLayout::Episode
+                    }
+                    None => cli::DownloadLayout::Flat,
+                },
+            };
+            let cover = match cover {
+                Some(cover) => cover,
+                None => match values.cover.as_deref().map(commands::settings::parse_cover) {
+                    Some(Ok(cover)) => cover,
+                    Some(Err(e)) => {
+                        t.error(format!("Invalid cover setting: {}", e));
+                        return Ok(1);
+                    }
+                    None => cli::CoverMode::Auto,
+                },
+            };
+            let default_retry = RetryPolicy::default();
+            let retries = retries
+                .or(values.retries)
+                .unwrap_or(default_retry.max_retries);
+            let retry_delay = retry_delay
+                .or(values.retry_delay)
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default_retry.base_delay);
+            let dir_template = match Template::parse_directory(
+                dir_template
+                    .as_deref()
+                    .or(values.dir_template.as_deref())
+                    .unwrap_or(DEFAULT_DIRECTORY_TEMPLATE),
+            ) {
+                Ok(template) => template,
+                Err(e) => {
+                    t.error(format!("Invalid directory template: {}", e));
+                    return Ok(1);
+                }
+            };
+            let file_template = match Template::parse_file(
+                file_template
+                    .as_deref()
+                    .or(values.file_template.as_deref())
+                    .unwrap_or(DEFAULT_FILE_TEMPLATE),
+            ) {
+                Ok(template) => template,
+                Err(e) => {
+                    t.error(format!("Invalid file template: {}", e));
+                    return Ok(1);
+                }
+            };
+            let reencode = transcode.is_some() || grayscale;
+            let transcode = match (transcode, grayscale, quality) {
+                (None, false, None) => None,
+                (format, _, _) => Some(TranscodeOptions {
+                    format: format.unwrap_or(cli::TranscodeFormat::Original).into(),
+                    quality: quality.unwrap_or(DEFAULT_QUALITY),
+                    grayscale,
+                }),
+            };
+            if gamma.is_some_and(|gamma| gamma <= 0.0) {
+                t.error("Invalid gamma: it must be above 0");
+                return Ok(1);
+            }
+            let profile = match device.as_deref().map(find_profile) {
+                Some(None) => {
+                    t.error(format!("Unknown device: {}", device.unwrap_or_default()));
+                    return Ok(1);
+                }
+                Some(profile) => profile,
+                None => None,
+            };
+            let device = match profile {
+                None if !trim && split.is_none() && gamma.is_none() && contrast.is_none() => None,
+                profile => {
+                    let base = profile.map(DeviceOptions::from_profile).unwrap_or_default();
+                    Some(DeviceOptions {
+                        trim,
+                        split: split.map(Into::into),
+                        gamma: gamma.unwrap_or(base.gamma),
+                        contrast: contrast.unwrap_or(base.contrast),
+                        ..base
+                    })
+                }
+            };
+            // The pages are only encoded again when transcoding or processing them
+            if quality.is_some() && !reencode && device.is_none() {
+                t.error("Invalid quality: it only applies with --transcode, --grayscale, or the device options");
+                return Ok(1);
+            }
+            let options = commands::download::DownloadOptions {
+                output: output
+                    .or(values.output)
+                    .unwrap_or_else(|| std::path::PathBuf::from(cli::DEFAULT_OUTPUT)),
+                layout,
+                cover,
+                dir_template,
+                file_template,
+                jobs,
+                format,
+                per_episode,
+                transcode,
+                device,
+                spreads,
+                keep_encrypted,
+                retry: RetryPolicy::new(retries, retry_delay),
+            };
+
+            commands::download::manga_download(&mut client, &t, slug, volume, options).await
+        }
+        cli::EmancipatedCommands::Config { .. } => 0,
+        cli::EmancipatedCommands::Decrypt { .. } => 0,
+        cli::EmancipatedCommands::Doctor { .. } => 0,
+        cli::EmancipatedCommands::Export { .. } => 0,
         cli::EmancipatedCommands::Info { slug } => {
             commands::manga::manga_info(&mut client, &t, slug).await
         }
         cli::EmancipatedCommands::Purchased => {
-            t.warn("Purchased command not implemented yet!");
-            1
+            commands::manga::manga_purchased(&mut client, &t).await
         }
         cli::EmancipatedCommands::Search { query } => {
             commands::manga::manga_search(&mut client, &t, query).await
@@ -91,3 +415,17 @@ async fn entrypoint(cli: EmancipatedCli) -> Result<ExitCode, ClientError> {
 
     Ok(exit_code)
 }
+
+/// Parse the proxy URL, returns `None` if it's invalid
+fn parse_proxy(proxy: Option<&str>, console: &term::Terminal) -> Option<Option<reqwest::Proxy>> {
+    match proxy {
+        Some(proxy) => match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => Some(Some(proxy)),
+            Err(e) => {
+                console.warn(format!("Unable to parse proxy: {}", e));
+                None
+            }
+        },
+        None => Some(None),
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+
+use rsa::sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{Comic, Volume},
+    spread::SpreadMode,
+};
+
+/// The filename of the manifest inside each volume directory
+pub const MANIFEST_FILENAME: &str = "_manifest.json";
+
+/// A single downloaded page recorded in the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestPage {
+    /// The page index in the volume, `0` is the cover if it is included
+    pub index: usize,
+    /// The episode number this page belongs to, `None` for the cover
+    pub episode: Option<i32>,
+    /// The original URL of the page
+    pub url: String,
+    /// The final filename of the page (relative to the volume directory)
+    pub filename: String,
+    /// SHA-256 hash of the final file
+    pub hash: String,
+    /// The other files produced from this page, in reading order after `filename`
+    ///
+    /// This is used when a landscape spread is split into two pages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_files: Vec<ManifestFile>,
+    /// The volume hash to derive the AES key, only set while the page is kept encrypted
+    ///
+    /// See [`decrypt_page`](crate::decrypt::decrypt_page).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_with: Option<String>,
+    /// The width and height of the main file, recorded when it is saved
+    ///
+    /// This is used to skip the pages that can not be a spread without decoding them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// An additional file produced from a single page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The filename relative to the volume directory
+    pub filename: String,
+    /// SHA-256 hash of the file
+    pub hash: String,
+}
+
+/// A single image of the volume, see [`VolumeManifest::images`]
+#[derive(Debug, Clone, Copy)]
+pub struct ManifestImage<'a> {
+    /// The page this image belongs to
+    pub page: &'a ManifestPage,
+    /// The position in the page files, `0` is the main file and a spread comes after the extra files
+    pub part: usize,
+    /// The filename relative to the volume directory
+    pub filename: &'a str,
+}
+
+impl ManifestPage {
+    /// Get all the files of this page in reading order, as `(filename, hash)`
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str)> {
+        std::iter::once((self.filename.as_str(), self.hash.as_str())).chain(
+            self.extra_files
+                .iter()
+                .map(|f| (f.filename.as_str(), f.hash.as_str())),
+        )
+    }
+}
+
+/// A per-volume manifest of the downloaded pages
+///
+/// This is used to resume interrupted downloads and to quickly recognize a finished volume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeManifest {
+    /// The slug of the title
+    pub slug: String,
+    /// The volume number
+    pub volume: i32,
+    /// Total pages in the volume, including the cover
+    pub total_pages: usize,
+    /// A fingerprint of the options that change the saved pages, e.g. the layout or transcoding
+    ///
+    /// The pages are downloaded again when the options change, see [`VolumeManifest::status`].
+    #[serde(default)]
+    pub options: String,
+    /// Is the volume fully downloaded
+    pub complete: bool,
+    /// The comic metadata, used for exporting
+    #[serde(default)]
+    pub comic: Option<Comic>,
+    /// The volume metadata, used for exporting
+    #[serde(default)]
+    pub volume_info: Option<Volume>,
+    /// The account used to download the volume, the private key is needed for the encrypted pages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// All the downloaded pages
+    pub pages: Vec<ManifestPage>,
+    /// The double-page spreads merged from two consecutive pages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spreads: Vec<ManifestSpread>,
+    /// How the spreads were merged, `None` if they were never looked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread_mode: Option<SpreadMode>,
+}
+
+/// How a loaded manifest relates to the volume being downloaded, see [`VolumeManifest::status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestStatus {
+    /// Every page is downloaded with the same options
+    Complete,
+    /// Some pages are missing, the download can resume
+    Partial,
+    /// The pages were saved with different options and must be downloaded again
+    Outdated,
+    /// The manifest belongs to another title or volume in the same directory
+    OtherVolume,
+}
+
+/// A double-page spread merged from two consecutive pages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSpread {
+    /// The index of the first page in reading order, the second page is the next index
+    pub first: usize,
+    /// The filename of the merged image relative to the volume directory
+    pub filename: String,
+    /// SHA-256 hash of the merged image
+    pub hash: String,
+    /// Use the spread instead of the two pages, the pages are still kept on disk
+    pub replace: bool,
+}
+
+impl VolumeManifest {
+    /// Create a new empty manifest
+    pub fn new(slug: impl Into<String>, volume: i32, total_pages: usize) -> Self {
+        Self {
+            slug: slug.into(),
+            volume,
+            total_pages,
+            options: String::new(),
+            complete: false,
+            comic: None,
+            volume_info: None,
+            account: None,
+            pages: vec![],
+            spreads: vec![],
+            spread_mode: None,
+        }
+    }
+
+    /// Get the manifest path for the volume directory
+    pub fn path(directory: &Path) -> PathBuf {
+        directory.join(MANIFEST_FILENAME)
+    }
+
+    /// Load the manifest from the volume directory
+    ///
+    /// Returns `None` if the manifest does not exist.
+    pub fn load(directory: &Path) -> Result<Option<Self>, std::io::Error> {
+        let path = Self::path(directory);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = std::fs::File::open(path)?;
+        let manifest: Self = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Save the manifest to the volume directory
+    ///
+    /// The file is replaced atomically, so an interrupted save never leaves a truncated manifest.
+    pub fn save(&self, directory: &Path) -> Result<(), std::io::Error> {
+        Self::save_bytes(directory, &self.to_bytes()?)
+    }
+
+    /// Serialize the manifest, to be saved later with [`VolumeManifest::save_bytes`]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, std::io::Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Atomically save the already serialized manifest to the volume directory
+    pub fn save_bytes(directory: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+        crate::config::write_atomic(&Self::path(directory), data)
+    }
+
+    /// Get the recorded page by the index
+    pub fn get_page(&self, index: usize) -> Option<&ManifestPage> {
+        self.pages.iter().find(|p| p.index == index)
+    }
+
+    /// Record a downloaded page, replacing any existing entry with the same index
+    pub fn add_page(&mut self, page: ManifestPage) {
+        self.pages.retain(|p| p.index != page.index);
+        self.pages.push(page);
+        self.pages.sort_by_key(|p| p.index);
+    }
+
+    /// Forget the recorded page, the file is kept on disk
+    pub fn remove_page(&mut self, index: usize) {
+        self.pages.retain(|p| p.index != index);
+    }
+
+    /// Check if the page is recorded, exists on disk, and the hash still matches
+    pub fn is_page_complete(&self, directory: &Path, index: usize) -> bool {
+        match self.get_page(index) {
+            Some(page) => {
+                page.files().all(
+                    |(filename, hash)| match hash_file(&directory.join(filename)) {
+                        Ok(actual) => actual == hash,
+                        Err(_) => false,
+                    },
+                )
+            }
+            None => false,
+        }
+    }
+
+    /// Get every image of the volume in reading order, including the extra files of each page
+    ///
+    /// A spread comes right after the second page, or replaces both pages if [`ManifestSpread::replace`] is set.
+    pub fn images(&self) -> Vec<ManifestImage<'_>> {
+        let mut images = vec![];
+        for page in self.pages.iter() {
+            let as_first = self.spreads.iter().find(|s| s.first == page.index);
+            let as_second = self.spreads.iter().find(|s| s.first + 1 == page.index);
+
+            let replaced =
+                as_first.is_some_and(|s| s.replace) || as_second.is_some_and(|s| s.replace);
+            if !replaced {
+                images.extend(page.files().enumerate().map(|(part, (filename, _))| {
+                    ManifestImage {
+                        page,
+                        part,
+                        filename,
+                    }
+                }));
+            }
+
+            if let Some(spread) = as_second {
+                images.push(ManifestImage {
+                    page,
+                    part: page.extra_files.len() + 1,
+                    filename: &spread.filename,
+                });
+            }
+        }
+
+        images
+    }
+
+    /// Check if any page is still encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.pages.iter().any(|p| p.encrypted_with.is_some())
+    }
+
+    /// Compare the manifest with the volume about to be downloaded
+    ///
+    /// `options` is the fingerprint of the current download options, see [`VolumeManifest::options`].
+    pub fn status(
+        &self,
+        directory: &Path,
+        slug: &str,
+        volume: i32,
+        total_pages: usize,
+        options: &str,
+    ) -> ManifestStatus {
+        if self.slug != slug || self.volume != volume {
+            ManifestStatus::OtherVolume
+        } else if self.options != options {
+            ManifestStatus::Outdated
+        } else if self.total_pages == total_pages && self.is_complete(directory) {
+            ManifestStatus::Complete
+        } else {
+            ManifestStatus::Partial
+        }
+    }
+
+    /// Quickly check if the volume is finished
+    ///
+    /// This only checks the completion flag and that all the pages exist, without verifying the hash.
+    pub fn is_complete(&self, directory: &Path) -> bool {
+        self.complete
+            && self.pages.len() == self.total_pages
+            && self
+                .pages
+                .iter()
+                .flat_map(|p| p.files())
+                .all(|(filename, _)| directory.join(filename).exists())
+            && self
+                .spreads
+                .iter()
+                .all(|s| directory.join(&s.filename).exists())
+    }
+}
+
+/// Calculate the SHA-256 hash of a file as a hex string
+pub fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+    let data = std::fs::read(path)?;
+
+    Ok(hash_bytes(&data))
+}
+
+/// Calculate the SHA-256 hash of the bytes as a hex string
+pub fn hash_bytes(data: &[u8]) -> String {
+    let digest = Sha256::digest(data);
+
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
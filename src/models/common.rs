@@ -3,7 +3,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Image {
     pub url: String,
-    height: u32,
+    /// The page height in pixels, used to find the double-page spreads before decoding
+    pub height: u32,
 }
 
 /// Error response schema for GraphQL
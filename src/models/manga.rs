@@ -55,3 +55,19 @@ pub struct Volume {
     pub release_at: Option<String>,
     pub price: Option<String>,
 }
+
+/// A volume that the user owns, as returned by the user library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchasedVolume {
+    pub slug: String,
+    #[serde(rename = "volumeNumber")]
+    pub number: i32,
+    pub name: String,
+}
+
+/// A title in the user library alongside the volumes the user owns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchasedComic {
+    pub comic: Comic,
+    pub volumes: Vec<PurchasedVolume>,
+}
@@ -46,6 +46,12 @@ pub struct ComicContentsQuery {
     pub manga: ComicContents,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchasedQuery {
+    #[serde(rename = "userLibrary")]
+    pub library: Vec<PurchasedComic>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserInfoQuery {
     pub user: User,
@@ -0,0 +1,75 @@
+use std::{future::Future, time::Duration};
+
+use rand::Rng;
+
+/// Retry policy with exponential backoff and jitter
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of retries, `0` disables retrying
+    pub max_retries: u32,
+    /// The base delay for the first retry
+    pub base_delay: Duration,
+    /// The maximum delay between retries
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new retry policy
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            base_delay,
+            ..Default::default()
+        }
+    }
+
+    /// Get the delay before the next retry
+    ///
+    /// The delay grows exponentially with each attempt (starting from `0`), capped at `max_delay`,
+    /// and half of it is randomized to avoid all the requests retrying at the same time.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = 2u32.saturating_pow(attempt);
+        let backoff = self.base_delay.saturating_mul(exponent).min(self.max_delay);
+
+        let half = backoff / 2;
+        let jitter_ms = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+
+        half + Duration::from_millis(jitter_ms)
+    }
+
+    /// Run the operation until it succeeds, fails with an error that cannot be retried, or runs out of retries
+    ///
+    /// `on_retry` is called with the attempt number (starting from `1`), the delay, and the error before waiting.
+    pub async fn run<T, E, Fut>(
+        &self,
+        mut operation: impl FnMut() -> Fut,
+        is_retryable: impl Fn(&E) -> bool,
+        mut on_retry: impl FnMut(u32, Duration, &E),
+    ) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(err) if attempt < self.max_retries && is_retryable(&err) => {
+                    let delay = self.delay(attempt);
+                    attempt += 1;
+                    on_retry(attempt, delay, &err);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! The global `settings.toml` file stored in the user path.
+//!
+//! Every value is optional and resolved in the following order, later one wins:
+//! 1. The global values at the top of the file
+//! 2. The per-account values in the `[accounts."<email>"]` table
+//! 3. The `EMANCIPATED_*` environment variables
+//!
+//! The CLI flags are applied on top of the resolved values by the caller.
+//!
+//! ```toml
+//! output = "/home/user/Manga"
+//! jobs = 4
+//! format = "cbz"
+//! dir_template = "{title}/Volume {volume:02}"
+//!
+//! [accounts."me@example.com"]
+//! format = "epub"
+//! ```
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{get_user_path, write_atomic, ConfigError},
+    template::Template,
+};
+
+/// The filename of the settings inside the user path
+pub const SETTINGS_FILENAME: &str = "settings.toml";
+
+/// Error type for the settings
+pub enum SettingsError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    Config(ConfigError),
+    /// The key is not a known setting
+    UnknownKey(String),
+    /// The value is not valid for the key
+    InvalidValue(String, String),
+}
+
+impl From<std::io::Error> for SettingsError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SettingsError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for SettingsError {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::Serialize(e)
+    }
+}
+
+impl From<ConfigError> for SettingsError {
+    fn from(e: ConfigError) -> Self {
+        Self::Config(e)
+    }
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Parse(e) => write!(f, "Parse Error: {}", e),
+            Self::Serialize(e) => write!(f, "Serialize Error: {}", e),
+            Self::Config(e) => write!(f, "{}", e),
+            Self::UnknownKey(key) => write!(
+                f,
+                "Unknown setting `{}`, available: {}",
+                key,
+                SettingsValues::KEYS.join(", ")
+            ),
+            Self::InvalidValue(key, value) => {
+                write!(f, "Invalid value `{}` for setting `{}`", value, key)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO Error: {:?}", e),
+            Self::Parse(e) => write!(f, "Parse Error: {:?}", e),
+            Self::Serialize(e) => write!(f, "Serialize Error: {:?}", e),
+            Self::Config(e) => write!(f, "{:?}", e),
+            Self::UnknownKey(key) => write!(f, "Unknown setting `{}`", key),
+            Self::InvalidValue(key, value) => {
+                write!(f, "Invalid value `{}` for setting `{}`", value, key)
+            }
+        }
+    }
+}
+
+/// The values that can be configured, either globally or per account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsValues {
+    /// The root directory for the downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Maximum concurrent page downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Proxy used for all requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// The output format of the downloaded volumes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// How many times to retry a failed page download
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// The base delay in milliseconds before retrying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u64>,
+    /// How the pages are laid out inside the volume directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Whether to download the volume cover
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// The template for the volume directory, see [`crate::template`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_template: Option<String>,
+    /// The template for the page filename, see [`crate::template`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_template: Option<String>,
+}
+
+impl SettingsValues {
+    /// All the available setting keys
+    pub const KEYS: &'static [&'static str] = &[
+        "output",
+        "jobs",
+        "proxy",
+        "format",
+        "retries",
+        "retry_delay",
+        "layout",
+        "cover",
+        "dir_template",
+        "file_template",
+    ];
+
+    /// Get the environment variable name for the key, e.g. `retry_delay` is `EMANCIPATED_RETRY_DELAY`
+    pub fn env_name(key: &str) -> String {
+        format!("EMANCIPATED_{}", key.to_uppercase())
+    }
+
+    /// Read the values from the `EMANCIPATED_*` environment variables
+    pub fn from_env() -> Result<Self, SettingsError> {
+        let (values, errors) = Self::from_env_lossy();
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(values),
+        }
+    }
+
+    /// Read the values from the `EMANCIPATED_*` environment variables, skipping the invalid ones
+    ///
+    /// Returns the valid values and the errors of the skipped ones.
+    pub fn from_env_lossy() -> (Self, Vec<SettingsError>) {
+        let mut values = Self::default();
+        let mut errors = vec![];
+        for key in Self::KEYS {
+            if let Ok(value) = std::env::var(Self::env_name(key)) {
+                if !value.is_empty() {
+                    if let Err(e) = values.set(key, &value) {
+                        errors.push(e);
+                    }
+                }
+            }
+        }
+
+        (values, errors)
+    }
+
+    /// Set the value from a string
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        let invalid = || SettingsError::InvalidValue(key.to_string(), value.to_string());
+
+        match key {
+            "output" => self.output = Some(PathBuf::from(value)),
+            "jobs" => {
+                let jobs: usize = value.parse().map_err(|_| invalid())?;
+                if jobs == 0 {
+                    return Err(invalid());
+                }
+                self.jobs = Some(jobs);
+            }
+            "proxy" => {
+                reqwest::Proxy::all(value).map_err(|_| invalid())?;
+                self.proxy = Some(value.to_string());
+            }
+            "format" => self.format = Some(value.to_lowercase()),
+            "retries" => self.retries = Some(value.parse().map_err(|_| invalid())?),
+            "retry_delay" => self.retry_delay = Some(value.parse().map_err(|_| invalid())?),
+            "layout" => self.layout = Some(value.to_lowercase()),
+            "cover" => self.cover = Some(value.to_lowercase()),
+            "dir_template" => {
+                Template::parse_directory(value).map_err(|_| invalid())?;
+                self.dir_template = Some(value.to_string());
+            }
+            "file_template" => {
+                Template::parse_file(value).map_err(|_| invalid())?;
+                self.file_template = Some(value.to_string());
+            }
+            _ => return Err(SettingsError::UnknownKey(key.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Remove the value
+    pub fn unset(&mut self, key: &str) -> Result<(), SettingsError> {
+        match key {
+            "output" => self.output = None,
+            "jobs" => self.jobs = None,
+            "proxy" => self.proxy = None,
+            "format" => self.format = None,
+            "retries" => self.retries = None,
+            "retry_delay" => self.retry_delay = None,
+            "layout" => self.layout = None,
+            "cover" => self.cover = None,
+            "dir_template" => self.dir_template = None,
+            "file_template" => self.file_template = None,
+            _ => return Err(SettingsError::UnknownKey(key.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Get the value as a string
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "output" => self.output.as_ref().map(|o| o.display().to_string()),
+            "jobs" => self.jobs.map(|j| j.to_string()),
+            "proxy" => self.proxy.clone(),
+            "format" => self.format.clone(),
+            "retries" => self.retries.map(|r| r.to_string()),
+            "retry_delay" => self.retry_delay.map(|r| r.to_string()),
+            "layout" => self.layout.clone(),
+            "cover" => self.cover.clone(),
+            "dir_template" => self.dir_template.clone(),
+            "file_template" => self.file_template.clone(),
+            _ => None,
+        }
+    }
+
+    /// Merge the other values on top of this one
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            output: other.output.clone().or_else(|| self.output.clone()),
+            jobs: other.jobs.or(self.jobs),
+            proxy: other.proxy.clone().or_else(|| self.proxy.clone()),
+            format: other.format.clone().or_else(|| self.format.clone()),
+            retries: other.retries.or(self.retries),
+            retry_delay: other.retry_delay.or(self.retry_delay),
+            layout: other.layout.clone().or_else(|| self.layout.clone()),
+            cover: other.cover.clone().or_else(|| self.cover.clone()),
+            dir_template: other
+                .dir_template
+                .clone()
+                .or_else(|| self.dir_template.clone()),
+            file_template: other
+                .file_template
+                .clone()
+                .or_else(|| self.file_template.clone()),
+        }
+    }
+}
+
+/// The `settings.toml` file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// The global values
+    #[serde(flatten)]
+    pub defaults: SettingsValues,
+    /// The per-account overrides, keyed by email
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, SettingsValues>,
+}
+
+impl Settings {
+    /// Get the path of the settings file
+    pub fn path() -> Result<PathBuf, ConfigError> {
+        Ok(get_user_path()?.join(SETTINGS_FILENAME))
+    }
+
+    /// Load the settings, returns the default settings if the file does not exist
+    pub fn load() -> Result<Self, SettingsError> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Save the settings to the user path
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let user_path = get_user_path()?;
+        std::fs::create_dir_all(&user_path)?;
+
+        let content = toml::to_string_pretty(self)?;
+        write_atomic(&Self::path()?, content.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Get the values for the account, or the global values if `None`
+    pub fn values_mut(&mut self, account: Option<&str>) -> &mut SettingsValues {
+        match account {
+            Some(account) => self.accounts.entry(account.to_string()).or_default(),
+            None => &mut self.defaults,
+        }
+    }
+
+    /// Resolve the effective values for the account, including the environment variables
+    pub fn resolve(&self, account: Option<&str>) -> Result<SettingsValues, SettingsError> {
+        Ok(self
+            .file_values(account)
+            .merge(&SettingsValues::from_env()?))
+    }
+
+    /// Resolve the effective values like [`Settings::resolve`], but ignore the invalid environment variables
+    ///
+    /// Returns the values and the errors of the ignored environment variables.
+    pub fn resolve_lossy(&self, account: Option<&str>) -> (SettingsValues, Vec<SettingsError>) {
+        let (env, errors) = SettingsValues::from_env_lossy();
+        (self.file_values(account).merge(&env), errors)
+    }
+
+    /// The values from the settings file, with the account overrides
+    fn file_values(&self, account: Option<&str>) -> SettingsValues {
+        let values = self.defaults.clone();
+        match account.and_then(|a| self.accounts.get(a)) {
+            Some(overrides) => values.merge(overrides),
+            None => values,
+        }
+    }
+}
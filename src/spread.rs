@@ -0,0 +1,210 @@
+//! Detect and merge the double-page spreads delivered as two separate pages.
+//!
+//! Two consecutive portrait pages with the same height are a spread when the artwork continues
+//! across the gutter, the left edge of the first page (the right page in a manga) matches
+//! the right edge of the second page.
+//!
+//! ```
+//! use emancipated::spread::{is_spread, merge_spread};
+//! use image::{DynamicImage, GrayImage, Luma};
+//!
+//! // A horizontal gradient split into two pages, the right half comes first
+//! let full = GrayImage::from_fn(200, 150, |x, y| Luma([((x + y) % 256) as u8]));
+//! let first = DynamicImage::ImageLuma8(image::imageops::crop_imm(&full, 100, 0, 100, 150).to_image());
+//! let second = DynamicImage::ImageLuma8(image::imageops::crop_imm(&full, 0, 0, 100, 150).to_image());
+//!
+//! assert!(is_spread(&first, &second));
+//! assert_eq!(merge_spread(&first, &second).to_luma8(), full);
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use image::{imageops, DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    image::ImageError,
+    manifest::{hash_bytes, ManifestPage, ManifestSpread, VolumeManifest},
+    transcode::{encode, TranscodeOptions},
+};
+
+/// How the merged spreads are used, see [`ManifestSpread::replace`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpreadMode {
+    /// The spread comes after the two pages
+    Alongside,
+    /// The spread is used instead of the two pages
+    Replace,
+}
+
+/// The minimum standard deviation of the edges, blank margins always match so they are ignored
+const MIN_EDGE_DEVIATION: f32 = 8.0;
+/// How much bigger the difference across the gutter can be than the difference inside each page
+const GUTTER_RATIO: f32 = 2.0;
+/// Allowed difference across the gutter on top of [`GUTTER_RATIO`], for the compression noise
+const GUTTER_TOLERANCE: f32 = 12.0;
+
+/// Check if the two consecutive pages form a spread
+///
+/// `first` is the first page in reading order, which is the right half of the spread.
+pub fn is_spread(first: &DynamicImage, second: &DynamicImage) -> bool {
+    if !may_be_spread(
+        (first.width(), first.height()),
+        (second.width(), second.height()),
+    ) {
+        return false;
+    }
+
+    let first = first.to_luma8();
+    let second = second.to_luma8();
+
+    // The gutter is between the left edge of the first page and the right edge of the second page
+    let first_edge = column(&first, 0);
+    let second_edge = column(&second, second.width() - 1);
+    if deviation(&first_edge) < MIN_EDGE_DEVIATION || deviation(&second_edge) < MIN_EDGE_DEVIATION {
+        return false;
+    }
+
+    let gutter = mean_difference(&first_edge, &second_edge);
+    let inner = (mean_difference(&first_edge, &column(&first, 1))
+        + mean_difference(&second_edge, &column(&second, second.width() - 2)))
+        / 2.0;
+
+    gutter <= inner * GUTTER_RATIO + GUTTER_TOLERANCE
+}
+
+/// Check the sizes of the two pages, only portrait pages with the same height can be a spread
+fn may_be_spread(first: (u32, u32), second: (u32, u32)) -> bool {
+    let is_portrait = |(width, height): (u32, u32)| width >= 2 && width < height;
+
+    first.1 == second.1 && is_portrait(first) && is_portrait(second)
+}
+
+/// Check the dimensions recorded in the manifest, the pages without them are always checked
+fn may_be_spread_page(first: &ManifestPage, second: &ManifestPage) -> bool {
+    match (first.dimensions, second.dimensions) {
+        (Some(first), Some(second)) => may_be_spread(first, second),
+        _ => true,
+    }
+}
+
+/// Merge the two pages into a single right-to-left spread, `first` is placed on the right
+pub fn merge_spread(first: &DynamicImage, second: &DynamicImage) -> DynamicImage {
+    let width = first.width() + second.width();
+    let height = first.height().max(second.height());
+
+    let has_color = first.color().has_color() || second.color().has_color();
+    let has_alpha = first.color().has_alpha() || second.color().has_alpha();
+    let mut merged = match (has_color, has_alpha) {
+        (true, true) => DynamicImage::new_rgba8(width, height),
+        (true, false) => DynamicImage::new_rgb8(width, height),
+        (false, true) => DynamicImage::new_luma_a8(width, height),
+        (false, false) => DynamicImage::new_luma8(width, height),
+    };
+
+    imageops::replace(&mut merged, second, 0, 0);
+    imageops::replace(&mut merged, first, second.width() as i64, 0);
+
+    merged
+}
+
+/// Detect the spreads in the downloaded volume and save the merged images next to the second page
+///
+/// `candidates` are the indexes of the first page of each possible pair, e.g. the consecutive pages
+/// with the same [`Image::height`](crate::models::Image::height). Pages that were split into multiple
+/// files are skipped, and a page is never part of two spreads.
+///
+/// The pairs that can not be a spread by their [`ManifestPage::dimensions`] are skipped without
+/// decoding them, and each page is decoded at most once.
+///
+/// Returns the spreads found, the manifest is not modified.
+pub fn merge_spreads(
+    directory: &Path,
+    manifest: &VolumeManifest,
+    candidates: &[usize],
+    mode: SpreadMode,
+    options: &TranscodeOptions,
+) -> Result<Vec<ManifestSpread>, ImageError> {
+    let mut spreads: Vec<ManifestSpread> = vec![];
+    // The second page of the previous pair is the first page of the next one
+    let mut decoded: Option<(usize, DynamicImage, image::ImageFormat)> = None;
+    for &index in candidates {
+        if spreads.last().is_some_and(|s| s.first + 1 == index) {
+            continue;
+        }
+
+        let (first, second) = match (manifest.get_page(index), manifest.get_page(index + 1)) {
+            (Some(first), Some(second))
+                if first.extra_files.is_empty()
+                    && second.extra_files.is_empty()
+                    && may_be_spread_page(first, second) =>
+            {
+                (first, second)
+            }
+            _ => continue,
+        };
+
+        let (first_image, source) = match decoded.take() {
+            Some((decoded_index, image, source)) if decoded_index == index => (image, source),
+            _ => decode_page(directory, first)?,
+        };
+        let (second_image, second_source) = decode_page(directory, second)?;
+        if !is_spread(&first_image, &second_image) {
+            decoded = Some((index + 1, second_image, second_source));
+            continue;
+        }
+
+        let merged = merge_spread(&first_image, &second_image);
+        let (data, ext) = encode(merged, source, options)?;
+        let filename = spread_filename(&second.filename, ext);
+        std::fs::write(directory.join(&filename), &data)?;
+
+        spreads.push(ManifestSpread {
+            first: index,
+            filename: filename.to_string_lossy().replace('\\', "/"),
+            hash: hash_bytes(&data),
+            replace: mode == SpreadMode::Replace,
+        });
+    }
+
+    Ok(spreads)
+}
+
+/// Decode the main file of the page, returns the image and its format
+fn decode_page(
+    directory: &Path,
+    page: &ManifestPage,
+) -> Result<(DynamicImage, image::ImageFormat), ImageError> {
+    let data = std::fs::read(directory.join(&page.filename))?;
+    let source = image::guess_format(&data)?;
+
+    Ok((image::load_from_memory_with_format(&data, source)?, source))
+}
+
+/// Name the spread after the second page, e.g. `e001/p006_spread.png`
+fn spread_filename(second: &str, ext: &str) -> PathBuf {
+    let path = Path::new(second);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!("{}_spread.{}", stem, ext))
+}
+
+fn column(image: &GrayImage, x: u32) -> Vec<f32> {
+    (0..image.height())
+        .map(|y| image.get_pixel(x, y)[0] as f32)
+        .collect()
+}
+
+fn mean_difference(a: &[f32], b: &[f32]) -> f32 {
+    let total: f32 = a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum();
+    total / a.len().max(1) as f32
+}
+
+fn deviation(values: &[f32]) -> f32 {
+    let count = values.len().max(1) as f32;
+    let mean = values.iter().sum::<f32>() / count;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / count;
+
+    variance.sqrt()
+}
@@ -0,0 +1,409 @@
+//! Templates for the output directory and the page filenames.
+//!
+//! `{name}` is replaced with the value of the variable, numeric variables can be zero padded
+//! with `{name:03}`. Use `{{` and `}}` for literal braces, and `/` to separate the directories.
+//!
+//! Available variables:
+//! - `{title}`: the title of the comic
+//! - `{slug}`: the slug of the comic
+//! - `{volume}`: the volume number
+//! - `{volume_name}`: the volume name
+//! - `{episode}`: the episode number, empty for the cover
+//! - `{page}`: the page number, `0` is the cover
+//! - `{ext}`: the image extension without the dot
+//!
+//! Every directory and filename is sanitized to be valid on both Windows and Linux,
+//! and a directory that ends up empty is skipped.
+//!
+//! ```
+//! use emancipated::template::{Template, TemplateValues};
+//!
+//! let template = Template::parse("{title}/Vol. {volume:02}").unwrap();
+//! let values = TemplateValues {
+//!     title: "Who: Are You?".to_string(),
+//!     volume: 1,
+//!     ..Default::default()
+//! };
+//!
+//! assert_eq!(
+//!     template.render(&values),
+//!     std::path::Path::new("Who_ Are You_").join("Vol. 01")
+//! );
+//! ```
+
+use std::path::PathBuf;
+
+use crate::models::{Comic, Volume};
+
+/// The default template for the volume directory
+pub const DEFAULT_DIRECTORY_TEMPLATE: &str = "{slug}/v{volume:02}";
+/// The default template for the page filename, relative to the volume directory
+pub const DEFAULT_FILE_TEMPLATE: &str = "p{page:03}.{ext}";
+
+/// Keep the filename well below the 255 bytes limit of most filesystems
+const MAX_FILENAME_BYTES: usize = 200;
+
+/// Error type when parsing the template
+pub enum TemplateError {
+    /// The variable does not exist
+    UnknownVariable(String),
+    /// The format specifier is invalid or used on a non-numeric variable
+    InvalidFormat(String),
+    /// A `{` or `}` without the pair
+    UnmatchedBrace,
+    /// The template is empty
+    Empty,
+    /// A required variable is not used, e.g. `{page}` in the file template
+    MissingVariable(&'static str),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownVariable(name) => write!(
+                f,
+                "Unknown variable `{{{}}}`, available: {}",
+                name,
+                Variable::ALL
+                    .iter()
+                    .map(|v| format!("{{{}}}", v.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::InvalidFormat(spec) => write!(
+                f,
+                "Invalid format `{{{}}}`, only numeric variables can be padded like `{{page:03}}`",
+                spec
+            ),
+            Self::UnmatchedBrace => write!(
+                f,
+                "Unmatched brace, use `{{{{` or `}}}}` for literal braces"
+            ),
+            Self::Empty => write!(f, "Template is empty"),
+            Self::MissingVariable(name) => write!(f, "Template must contain `{{{}}}`", name),
+        }
+    }
+}
+
+impl std::fmt::Debug for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownVariable(name) => write!(f, "Unknown variable `{}`", name),
+            Self::InvalidFormat(spec) => write!(f, "Invalid format `{}`", spec),
+            Self::UnmatchedBrace => write!(f, "Unmatched brace"),
+            Self::Empty => write!(f, "Template is empty"),
+            Self::MissingVariable(name) => write!(f, "Missing variable `{}`", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Title,
+    Slug,
+    Volume,
+    VolumeName,
+    Episode,
+    Page,
+    Ext,
+}
+
+impl Variable {
+    const ALL: [Variable; 7] = [
+        Self::Title,
+        Self::Slug,
+        Self::Volume,
+        Self::VolumeName,
+        Self::Episode,
+        Self::Page,
+        Self::Ext,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Slug => "slug",
+            Self::Volume => "volume",
+            Self::VolumeName => "volume_name",
+            Self::Episode => "episode",
+            Self::Page => "page",
+            Self::Ext => "ext",
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Volume | Self::Episode | Self::Page)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Variable(Variable, usize),
+    Separator,
+}
+
+/// The values available to the [`Template`]
+#[derive(Debug, Clone, Default)]
+pub struct TemplateValues {
+    pub title: String,
+    pub slug: String,
+    pub volume: i32,
+    pub volume_name: String,
+    /// `None` for the cover
+    pub episode: Option<i32>,
+    pub page: usize,
+    pub ext: String,
+}
+
+impl TemplateValues {
+    /// Create the values for the volume, the page values are empty
+    pub fn new(comic: &Comic, volume: &Volume) -> Self {
+        Self {
+            title: comic.title.clone(),
+            slug: comic.slug.clone(),
+            volume: volume.number,
+            volume_name: volume.name.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the page values
+    pub fn with_page(mut self, page: usize, episode: Option<i32>, ext: impl Into<String>) -> Self {
+        self.page = page;
+        self.episode = episode;
+        self.ext = ext.into();
+        self
+    }
+
+    fn get(&self, variable: Variable, width: usize) -> String {
+        match variable {
+            Variable::Title => self.title.clone(),
+            Variable::Slug => self.slug.clone(),
+            Variable::Volume => format!("{:0width$}", self.volume, width = width),
+            Variable::VolumeName => self.volume_name.clone(),
+            Variable::Episode => self
+                .episode
+                .map(|e| format!("{:0width$}", e, width = width))
+                .unwrap_or_default(),
+            Variable::Page => format!("{:0width$}", self.page, width = width),
+            Variable::Ext => self.ext.clone(),
+        }
+    }
+}
+
+/// A parsed path template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse the template, see the [module documentation](self) for the syntax
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(TemplateError::UnmatchedBrace),
+                            Some(c) => spec.push(c),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_variable(&spec)?);
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace),
+                '/' | '\\' => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Separator);
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if parts.iter().all(|p| *p == Part::Separator) {
+            return Err(TemplateError::Empty);
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Parse the template for the volume directory, it must contain `{volume}`
+    ///
+    /// Each volume needs its own directory since the manifest is saved inside it.
+    pub fn parse_directory(template: &str) -> Result<Self, TemplateError> {
+        Self::parse(template)?.require(&["volume"])
+    }
+
+    /// Parse the template for the page filename, it must contain `{page}` and `{ext}`
+    pub fn parse_file(template: &str) -> Result<Self, TemplateError> {
+        Self::parse(template)?.require(&["page", "ext"])
+    }
+
+    fn require(self, names: &[&'static str]) -> Result<Self, TemplateError> {
+        match names.iter().find(|name| !self.contains(name)) {
+            Some(name) => Err(TemplateError::MissingVariable(name)),
+            None => Ok(self),
+        }
+    }
+
+    /// Check if the template uses the variable, e.g. `page`
+    pub fn contains(&self, name: &str) -> bool {
+        self.parts
+            .iter()
+            .any(|p| matches!(p, Part::Variable(variable, _) if variable.name() == name))
+    }
+
+    /// Render the template into a relative path
+    pub fn render(&self, values: &TemplateValues) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+
+        for part in self.parts.iter() {
+            match part {
+                Part::Literal(text) => component.push_str(text),
+                Part::Variable(variable, width) => {
+                    component.push_str(&values.get(*variable, *width))
+                }
+                Part::Separator => push_component(&mut path, &mut component),
+            }
+        }
+        push_component(&mut path, &mut component);
+
+        if path.as_os_str().is_empty() {
+            path.push("_");
+        }
+
+        path
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn parse_variable(spec: &str) -> Result<Part, TemplateError> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (spec, None),
+    };
+
+    let variable = Variable::ALL
+        .into_iter()
+        .find(|v| v.name() == name)
+        .ok_or_else(|| TemplateError::UnknownVariable(name.to_string()))?;
+
+    let width = match format {
+        None => 0,
+        Some(format) if variable.is_numeric() => format
+            .parse()
+            .map_err(|_| TemplateError::InvalidFormat(spec.to_string()))?,
+        Some(_) => return Err(TemplateError::InvalidFormat(spec.to_string())),
+    };
+
+    Ok(Part::Variable(variable, width))
+}
+
+fn push_component(path: &mut PathBuf, component: &mut String) {
+    let component = std::mem::take(component);
+    if !component.trim().is_empty() {
+        path.push(sanitize_filename(&component));
+    }
+}
+
+/// Make the name safe to use as a single file or directory name on both Windows and Linux
+///
+/// - Path separators, reserved characters (`<>:"|?*`), and control characters are replaced with `_`
+/// - Trailing dots and spaces are removed, and the name is limited to 200 bytes
+///   by shortening the stem, so the extension is kept
+/// - Reserved device names like `CON` or `LPT1` are prefixed with `_`
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if sanitized.len() > MAX_FILENAME_BYTES {
+        let extension = match sanitized.rsplit_once('.') {
+            Some((stem, ext)) if is_extension(stem, ext) => format!(".{}", ext),
+            _ => String::new(),
+        };
+
+        let mut end = MAX_FILENAME_BYTES - extension.len();
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        let stem_len = sanitized.trim_end_matches(['.', ' ']).len();
+        sanitized.truncate(stem_len);
+        sanitized.push_str(&extension);
+    }
+
+    let sanitized = sanitized.trim_start().trim_end_matches(['.', ' ']);
+    if sanitized.is_empty() {
+        return "_".to_string();
+    }
+
+    let stem = sanitized
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    if is_reserved_name(&stem) {
+        return format!("_{}", sanitized);
+    }
+
+    sanitized.to_string()
+}
+
+/// A short alphanumeric suffix like `png` or `jpeg`, titles can contain dots too
+fn is_extension(stem: &str, ext: &str) -> bool {
+    !stem.is_empty()
+        && (1..=5).contains(&ext.len())
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_reserved_name(stem: &str) -> bool {
+    match stem {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => match stem
+            .strip_prefix("COM")
+            .or_else(|| stem.strip_prefix("LPT"))
+        {
+            Some(number) => number.len() == 1 && matches!(number.as_bytes()[0], b'1'..=b'9'),
+            None => false,
+        },
+    }
+}
@@ -2,7 +2,7 @@ use std::sync::LazyLock;
 
 use anstream::println;
 use color_print::cformat;
-use inquire::Select;
+use inquire::{Confirm, Password, PasswordDisplayMode, Select};
 
 pub(crate) static IS_WIN_VT_SUPPORTED: LazyLock<bool> = LazyLock::new(|| {
     if ::supports_hyperlinks::on(::supports_hyperlinks::Stream::Stdout) {
@@ -82,6 +82,7 @@ impl Terminal {
     // }
 
     /// Do a single choice prompt
+    #[allow(clippy::manual_unwrap_or_default)]
     pub fn choice(&self, prompt: &str, choices: Vec<ConsoleChoice>) -> Option<ConsoleChoice> {
         let choice = Select::new(prompt, choices).prompt_skippable();
 
@@ -91,7 +92,24 @@ impl Terminal {
         }
     }
 
-    /// Stop the current spinner
+    /// Do a yes/no prompt, defaults to no
+    pub fn confirm(&self, prompt: &str) -> bool {
+        let confirm = Confirm::new(prompt).with_default(false).prompt_skippable();
+
+        confirm.unwrap_or_default().unwrap_or(false)
+    }
+
+    /// Do a masked password prompt
+    pub fn password(&self, prompt: &str) -> Option<String> {
+        let password = Password::new(prompt)
+            .without_confirmation()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .prompt_skippable();
+
+        password.unwrap_or_default()
+    }
+
+    // /// Stop the current spinner
     // pub fn stop_status(&mut self) {
     //     match self.current_spinner.as_mut() {
     //         Some(spinner) => {
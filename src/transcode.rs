@@ -0,0 +1,145 @@
+//! Re-encode the decrypted pages into another format.
+//!
+//! ```
+//! use emancipated::transcode::{transcode, OutputFormat, TranscodeOptions};
+//!
+//! let mut png = std::io::Cursor::new(vec![]);
+//! image::RgbImage::new(4, 4)
+//!     .write_to(&mut png, image::ImageFormat::Png)
+//!     .unwrap();
+//!
+//! let options = TranscodeOptions {
+//!     format: OutputFormat::Jpeg,
+//!     quality: 80,
+//!     grayscale: true,
+//! };
+//! let (data, ext) = transcode(png.get_ref(), &options).unwrap();
+//! assert_eq!(ext, "jpg");
+//! assert_eq!(image::guess_format(&data).unwrap(), image::ImageFormat::Jpeg);
+//! ```
+
+use std::io::Cursor;
+
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType, PngEncoder},
+        webp::WebPEncoder,
+    },
+    DynamicImage, ImageFormat,
+};
+
+use crate::image::ImageError;
+
+/// The default JPEG quality
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// The target format of the transcoded image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Keep the original format, PNG is used for anything other than JPEG or WebP
+    Original,
+    /// Lossless PNG with the best compression
+    Png,
+    /// Lossy JPEG with the configured quality
+    Jpeg,
+    /// Lossless WebP
+    Webp,
+}
+
+/// The options for [`transcode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranscodeOptions {
+    pub format: OutputFormat,
+    /// The JPEG quality from 1 to 100, ignored for the lossless formats
+    pub quality: u8,
+    /// Convert the image to grayscale
+    pub grayscale: bool,
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Original,
+            quality: DEFAULT_QUALITY,
+            grayscale: false,
+        }
+    }
+}
+
+/// Decode the image and re-encode it with the options
+///
+/// Returns the encoded data and the extension without the dot.
+pub fn transcode(
+    data: &[u8],
+    options: &TranscodeOptions,
+) -> Result<(Vec<u8>, &'static str), ImageError> {
+    let source = image::guess_format(data)?;
+    let image = image::load_from_memory_with_format(data, source)?;
+
+    encode(image, source, options)
+}
+
+/// Encode the decoded image with the options
+///
+/// The `source` format is used for [`OutputFormat::Original`].
+pub fn encode(
+    image: DynamicImage,
+    source: ImageFormat,
+    options: &TranscodeOptions,
+) -> Result<(Vec<u8>, &'static str), ImageError> {
+    let image = if options.grayscale {
+        image.grayscale()
+    } else {
+        image
+    };
+
+    let format = match options.format {
+        OutputFormat::Original => match source {
+            ImageFormat::Jpeg => ImageFormat::Jpeg,
+            ImageFormat::WebP => ImageFormat::WebP,
+            _ => ImageFormat::Png,
+        },
+        OutputFormat::Png => ImageFormat::Png,
+        OutputFormat::Jpeg => ImageFormat::Jpeg,
+        OutputFormat::Webp => ImageFormat::WebP,
+    };
+
+    let mut output = Cursor::new(vec![]);
+    match format {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let image = if image.color().has_color() {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            } else {
+                DynamicImage::ImageLuma8(image.to_luma8())
+            };
+            let quality = options.quality.clamp(1, 100);
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut output, quality))?;
+        }
+        ImageFormat::WebP => {
+            to_8bit(image).write_with_encoder(WebPEncoder::new_lossless(&mut output))?;
+        }
+        _ => {
+            let encoder = PngEncoder::new_with_quality(
+                &mut output,
+                CompressionType::Best,
+                FilterType::Adaptive,
+            );
+            image.write_with_encoder(encoder)?;
+        }
+    }
+
+    Ok((output.into_inner(), format.extensions_str()[0]))
+}
+
+/// Convert to the 8-bit variant with the same channels, WebP only supports 8-bit images
+pub(crate) fn to_8bit(image: DynamicImage) -> DynamicImage {
+    let color = image.color();
+    match (color.has_color(), color.has_alpha()) {
+        (true, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (true, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, true) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        (false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+    }
+}
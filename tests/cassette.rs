@@ -0,0 +1,115 @@
+mod common;
+
+use common::{MockApi, AES_KEY, TEST_EMAIL, TEST_SLUG};
+use emancipated::{cassette::Cassette, config::Config, kp, Client, ClientError};
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    let cassette_dir = common::init_user_path().join("cassette_record_and_replay");
+    let _ = std::fs::remove_dir_all(&cassette_dir);
+
+    let api = MockApi::start().await;
+    api.mount_login(3600).await;
+    // Personal details that might come with the responses
+    let mut comic = common::comic_json();
+    comic["userId"] = "user-42".into();
+    comic["dateOfBirth"] = "1990-01-01".into();
+    comic["pronouns"] = "they/them".into();
+    api.mount_graphql("searchManga", serde_json::json!({ "search": [comic] }))
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .and(body_string_contains("getVolumes"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+        .mount(&api.server)
+        .await;
+
+    let response = Client::login_with_endpoints(TEST_EMAIL, "hunter2", None, &api.endpoints())
+        .await
+        .unwrap();
+    let mut config = Config::from(&response);
+    let mut client = Client::new_with_endpoints(&mut config, None, None, api.endpoints())
+        .unwrap()
+        .with_cassette(Cassette::record(&cassette_dir).unwrap());
+
+    let public_key = rsa::RsaPublicKey::from(client.get_private_key());
+    // Signed page URL, the signature should not be recorded
+    api.mount_contents(&public_key, &["p1?Signature=page-signature"])
+        .await;
+    let encrypted_page = common::encrypt_page(&common::sample_png(7), &AES_KEY);
+    api.mount_page("p1", encrypted_page.clone()).await;
+
+    let recorded_search = client.search("test").await.unwrap();
+    let recorded_contents = client.get_contents(TEST_SLUG, 1).await.unwrap();
+    let page_url = recorded_contents.contents.episodes[0].pages[0].url.clone();
+    let recorded_page = client.download_image(&page_url).await.unwrap();
+    assert_eq!(recorded_page, encrypted_page);
+
+    // The failures are recorded too
+    let failed = client.get_volumes(TEST_SLUG).await;
+    assert!(matches!(failed, Err(ClientError::Reqwest(_))));
+
+    // Nothing sensitive should be written to the cassette
+    let mut statuses = vec![];
+    for entry in std::fs::read_dir(cassette_dir.join("graphql")).unwrap() {
+        let content = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        for sensitive in [
+            "access-token-1",
+            "user-42",
+            "1990-01-01",
+            "they/them",
+            "page-signature",
+        ] {
+            assert!(!content.contains(sensitive));
+        }
+        assert!(content.contains("<redacted>"));
+        let entry: serde_json::Value = serde_json::from_str(&content).unwrap();
+        statuses.push(entry["status"].as_u64().unwrap());
+    }
+    statuses.sort();
+    assert_eq!(statuses, [200, 200, 503]);
+    for entry in std::fs::read_dir(cassette_dir.join("cdn")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "json") {
+            let content = std::fs::read_to_string(path).unwrap();
+            assert!(!content.contains("page-signature"));
+        }
+    }
+
+    // Replay without the server
+    let mut config = client.get_config_owned();
+    drop(api);
+
+    let endpoints = emancipated::ClientEndpoints::from_base_url("http://127.0.0.1:9");
+    let mut client = Client::new_with_endpoints(&mut config, None, None, endpoints)
+        .unwrap()
+        .with_cassette(Cassette::replay(&cassette_dir).unwrap());
+
+    let replayed_search = client.search("test").await.unwrap();
+    assert_eq!(replayed_search.len(), recorded_search.len());
+    assert_eq!(replayed_search[0].slug, recorded_search[0].slug);
+
+    let replayed_contents = client.get_contents(TEST_SLUG, 1).await.unwrap();
+    assert_eq!(
+        replayed_contents.contents.episodes[0].pages[0].url,
+        format!("{}?<redacted>", page_url.split_once('?').unwrap().0)
+    );
+    let aes_key =
+        kp::hash_to_aes_key(client.get_private_key(), &replayed_contents.contents.hash).unwrap();
+    assert_eq!(aes_key, AES_KEY);
+
+    let replayed_page = client.download_image(&page_url).await.unwrap();
+    assert_eq!(replayed_page, encrypted_page);
+    // The redacted URL from the replayed response maps to the same recording
+    let replayed_url = &replayed_contents.contents.episodes[0].pages[0].url;
+    let replayed_page = client.download_image(replayed_url).await.unwrap();
+    assert_eq!(replayed_page, encrypted_page);
+
+    // A request that was never recorded
+    let missing = client.search("something else").await;
+    assert!(matches!(missing, Err(ClientError::Cassette(_))));
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use common::{MockApi, AES_KEY, TEST_EMAIL, TEST_SLUG};
+use emancipated::{
+    config::Config,
+    image::{decode_image, decrypt_image, load_and_save_image, ImageError},
+    kp, Client, ClientError,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_string_contains, header, method, path},
+    Mock, ResponseTemplate,
+};
+
+async fn login_client(api: &MockApi, expires_in: i64) -> Client {
+    api.mount_login(expires_in).await;
+
+    let response = Client::login_with_endpoints(TEST_EMAIL, "hunter2", None, &api.endpoints())
+        .await
+        .unwrap();
+    let mut config = Config::from(&response);
+
+    Client::new_with_endpoints(&mut config, None, None, api.endpoints()).unwrap()
+}
+
+#[tokio::test]
+async fn test_login_and_user_info() {
+    let api = MockApi::start().await;
+    api.mount_user().await;
+
+    let mut client = login_client(&api, 3600).await;
+    assert_eq!(client.get_config().email(), TEST_EMAIL);
+    assert_eq!(client.get_config().access_token(), "access-token-1");
+
+    let user = client.get_user().await.unwrap();
+    assert_eq!(user.user.id, "user-1");
+    assert_eq!(user.user.coins, 120);
+}
+
+#[tokio::test]
+async fn test_search_and_volumes() {
+    let api = MockApi::start().await;
+    api.mount_search().await;
+    api.mount_volumes().await;
+
+    let mut client = login_client(&api, 3600).await;
+
+    let results = client.search("test").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].slug, TEST_SLUG);
+
+    let volumes = client.get_volumes(TEST_SLUG).await.unwrap();
+    assert_eq!(volumes.comic.title, "Test Manga");
+    assert_eq!(volumes.volumes.len(), 2);
+    assert!(volumes.volumes[0].purchased);
+    assert!(!volumes.volumes[1].purchased);
+}
+
+#[tokio::test]
+async fn test_download_and_decrypt_page() {
+    let api = MockApi::start().await;
+    let mut client = login_client(&api, 3600).await;
+
+    let public_key = rsa::RsaPublicKey::from(client.get_private_key());
+    api.mount_contents(&public_key, &["p1", "p2"]).await;
+
+    let original = common::sample_png(42);
+    api.mount_page("p1", common::encrypt_page(&original, &AES_KEY))
+        .await;
+
+    let contents = client.get_contents(TEST_SLUG, 1).await.unwrap();
+    assert_eq!(contents.contents.episodes.len(), 1);
+    assert_eq!(contents.contents.episodes[0].pages.len(), 2);
+
+    let aes_key = kp::hash_to_aes_key(client.get_private_key(), &contents.contents.hash).unwrap();
+    assert_eq!(aes_key, AES_KEY);
+
+    let page_url = &contents.contents.episodes[0].pages[0].url;
+    let encrypted = client.download_image(page_url).await.unwrap();
+
+    let output_dir = common::init_user_path().join("test_download_and_decrypt_page");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let saved = load_and_save_image(&encrypted, &aes_key, &output_dir.join("p001")).unwrap();
+    assert_eq!(saved.extension().unwrap(), "png");
+    assert_eq!(std::fs::read(&saved).unwrap(), original);
+
+    // The second page is not mounted on the CDN
+    let missing = client
+        .download_image(&contents.contents.episodes[0].pages[1].url)
+        .await;
+    assert!(matches!(missing, Err(ClientError::Reqwest(_))));
+}
+
+#[test]
+fn test_decode_cover_image() {
+    let original = common::sample_png(7);
+
+    // Served as is
+    let (data, ext) = decode_image(&original, &AES_KEY).unwrap();
+    assert_eq!(ext, "png");
+    assert_eq!(data, original);
+
+    // Encrypted like the other pages
+    let (data, ext) = decode_image(&common::encrypt_page(&original, &AES_KEY), &AES_KEY).unwrap();
+    assert_eq!(ext, "png");
+    assert_eq!(data, original);
+
+    // Truncated responses are errors instead of panics
+    assert!(matches!(
+        decrypt_image(&[], &AES_KEY),
+        Err(ImageError::AESLength(_))
+    ));
+    assert!(matches!(
+        decode_image(&[2, 0, 1], &AES_KEY),
+        Err(ImageError::AESLength(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_purchased_library() {
+    let api = MockApi::start().await;
+    let mut other = common::comic_json();
+    other["comicId"] = json!("comic-2");
+    other["slug"] = json!("other-manga");
+    other["title"] = json!("Other Manga");
+    let volume = |number: i32| {
+        json!({
+            "slug": format!("{}-{}", TEST_SLUG, number),
+            "volumeNumber": number,
+            "name": format!("Volume {}", number),
+        })
+    };
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .and(header("authorization", "Bearer access-token-1"))
+        .and(body_string_contains("query getUserLibrary"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": {
+                "userLibrary": [
+                    { "comic": common::comic_json(), "volumes": [volume(3), volume(1), volume(2)] },
+                    { "comic": other, "volumes": [] },
+                ]
+            }
+        })))
+        .expect(1)
+        .mount(&api.server)
+        .await;
+
+    let mut client = login_client(&api, 3600).await;
+
+    // The whole library comes in a single response, in the order of the API
+    let library = client.get_purchased().await.unwrap();
+    assert_eq!(library.len(), 2);
+    assert_eq!(library[0].comic.slug, TEST_SLUG);
+    let volumes: Vec<i32> = library[0].volumes.iter().map(|v| v.number).collect();
+    assert_eq!(volumes, [3, 1, 2]);
+    assert_eq!(library[0].volumes[0].name, "Volume 3");
+    assert_eq!(library[1].comic.title, "Other Manga");
+    assert!(library[1].volumes.is_empty());
+}
+
+#[tokio::test]
+async fn test_purchased_library_empty() {
+    let api = MockApi::start().await;
+    api.mount_graphql("getUserLibrary", json!({ "userLibrary": [] }))
+        .await;
+
+    let mut client = login_client(&api, 3600).await;
+
+    let library = client.get_purchased().await.unwrap();
+    assert!(library.is_empty());
+}
+
+#[tokio::test]
+async fn test_refresh_expired_token() {
+    let api = MockApi::start().await;
+    api.mount_refresh().await;
+
+    // Only respond to the refreshed token
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .and(header("authorization", "Bearer access-token-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "search": [common::comic_json()] }
+        })))
+        .expect(1)
+        .mount(&api.server)
+        .await;
+
+    let mut client = login_client(&api, -10).await;
+    assert!(client.get_config().is_expired());
+
+    let results = client.search("test").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(client.get_config().access_token(), "access-token-2");
+    assert_eq!(client.get_config().refresh_token(), "refresh-token-2");
+    assert!(!client.get_config().is_expired());
+}
+
+#[tokio::test]
+async fn test_graphql_error_response() {
+    let api = MockApi::start().await;
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "errors": [{
+                "message": "Volume not found",
+                "locations": [{ "line": 2, "column": 13 }],
+                "path": ["comicVolumes"],
+            }]
+        })))
+        .mount(&api.server)
+        .await;
+
+    let mut client = login_client(&api, 3600).await;
+
+    match client.get_volumes("missing").await {
+        Err(ClientError::GraphQLError(error)) => {
+            assert_eq!(error.errors.len(), 1);
+            assert_eq!(error.errors[0].message, "Volume not found");
+        }
+        other => panic!("expected a GraphQL error, got {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,264 @@
+//! Local stand-in for the GraphQL API, the Google auth endpoints, and the image CDN.
+
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+use aes_gcm::{aead::Aead, aes::Aes256, AesGcm, KeyInit};
+use base64::{engine::general_purpose, Engine as _};
+use emancipated::{
+    export::comicinfo::ComicInfo,
+    manifest::{hash_bytes, ManifestPage, VolumeManifest},
+    models::{Comic, Volume},
+    ClientEndpoints,
+};
+use rsa::{sha2::Sha256, RsaPublicKey};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+type Aes256Gcm16 = AesGcm<Aes256, aes_gcm::aead::consts::U16>;
+
+pub const TEST_EMAIL: &str = "reader@example.com";
+pub const TEST_SLUG: &str = "test-manga";
+pub const AES_KEY: [u8; 32] = [7u8; 32];
+
+static INIT_USER_PATH: Once = Once::new();
+
+/// Point the user path to a temporary directory so the tests never touch the real accounts
+pub fn init_user_path() -> PathBuf {
+    let user_path = std::env::temp_dir().join(format!("emancipated-test-{}", std::process::id()));
+    INIT_USER_PATH.call_once(|| {
+        std::fs::create_dir_all(&user_path).unwrap();
+        std::env::set_var(emancipated::config::USER_PATH_ENV, &user_path);
+    });
+    user_path
+}
+
+pub struct MockApi {
+    pub server: MockServer,
+}
+
+impl MockApi {
+    pub async fn start() -> Self {
+        init_user_path();
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    pub fn endpoints(&self) -> ClientEndpoints {
+        ClientEndpoints::from_base_url(self.server.uri())
+    }
+
+    pub fn page_url(&self, name: &str) -> String {
+        format!("{}/cdn/{}", self.server.uri(), name)
+    }
+
+    /// Mount a GraphQL response for the operation name
+    pub async fn mount_graphql(&self, operation: &str, data: Value) {
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains(operation))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": data })))
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn mount_login(&self, expires_in: i64) {
+        Mock::given(method("POST"))
+            .and(path("/v1/accounts:signInWithPassword"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "idToken": "access-token-1",
+                "expiresIn": expires_in.to_string(),
+                "refreshToken": "refresh-token-1",
+                "email": TEST_EMAIL,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn mount_refresh(&self) {
+        Mock::given(method("POST"))
+            .and(path("/v1/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "access-token-2",
+                "expires_in": "3600",
+                "token_type": "Bearer",
+                "refresh_token": "refresh-token-2",
+                "id_token": "access-token-2",
+                "user_id": "user-1",
+                "project_id": "project-1",
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn mount_user(&self) {
+        self.mount_graphql(
+            "getUserInfo",
+            json!({
+                "user": { "coins": 120, "userId": "user-1", "deletedAt": null },
+                "userProfile": { "userId": "user-1", "pronouns": null, "dateOfBirth": null },
+            }),
+        )
+        .await;
+    }
+
+    pub async fn mount_search(&self) {
+        self.mount_graphql("searchManga", json!({ "search": [comic_json()] }))
+            .await;
+    }
+
+    pub async fn mount_volumes(&self) {
+        self.mount_graphql(
+            "getVolumes",
+            json!({
+                "comicVolumes": {
+                    "comic": comic_json(),
+                    "volumes": [volume_json(1, true), volume_json(2, false)],
+                }
+            }),
+        )
+        .await;
+    }
+
+    /// Mount the volume contents, the AES key is encrypted with the client public key
+    pub async fn mount_contents(&self, public_key: &RsaPublicKey, pages: &[&str]) {
+        let pages: Vec<Value> = pages
+            .iter()
+            .map(|name| json!({ "url": self.page_url(name), "height": 16 }))
+            .collect();
+        let mut volume = volume_json(1, true);
+        volume["cover"]["url"] = json!(self.page_url("cover.jpg"));
+
+        self.mount_graphql(
+            "getMangaContents",
+            json!({
+                "manga": {
+                    "contents": {
+                        "episodes": [{ "episodeNumber": 1, "pages": pages }],
+                        "hash": encrypt_aes_key(public_key, &AES_KEY),
+                    },
+                    "volume": volume,
+                }
+            }),
+        )
+        .await;
+    }
+
+    /// Mount a raw file on the fake CDN
+    pub async fn mount_page(&self, name: &str, data: Vec<u8>) {
+        Mock::given(method("GET"))
+            .and(path(format!("/cdn/{}", name)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(data))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+pub fn comic_json() -> Value {
+    json!({
+        "comicId": "comic-1",
+        "slug": TEST_SLUG,
+        "title": "Test Manga",
+        "cover": { "url": "https://example.com/cover.jpg", "height": 100 },
+        "noVolume": false,
+        "genres": [{ "name": "Romance", "tagId": "tag-1" }],
+        "metadata": { "completed": false, "creators": ["Author A", "Artist B"] },
+    })
+}
+
+pub fn volume_json(number: i32, purchased: bool) -> Value {
+    json!({
+        "slug": format!("{}-{}", TEST_SLUG, number),
+        "volumeNumber": number,
+        "name": format!("Volume {}", number),
+        "purchased": purchased,
+        "readerSkipCover": false,
+        "releasesAt": "2024-01-01T00:00:00Z",
+        "price": if purchased { Value::Null } else { json!("$9.99") },
+        "cover": { "url": "https://example.com/cover.jpg", "height": 100 },
+    })
+}
+
+/// The comic and the first volume metadata
+pub fn volume_metadata() -> (Comic, Volume) {
+    (
+        serde_json::from_value(comic_json()).unwrap(),
+        serde_json::from_value(volume_json(1, true)).unwrap(),
+    )
+}
+
+pub fn comic_info() -> ComicInfo {
+    let (comic, volume) = volume_metadata();
+    ComicInfo::new(&comic, &volume)
+}
+
+/// Write the pages of a finished volume, as `(index, episode, filename, data)`
+///
+/// The nested directories of the filenames are created as needed.
+pub fn write_volume(
+    directory: &Path,
+    pages: Vec<(usize, Option<i32>, &str, Vec<u8>)>,
+) -> VolumeManifest {
+    let mut manifest = VolumeManifest::new(TEST_SLUG, 1, pages.len());
+    for (index, episode, filename, data) in pages {
+        let path = directory.join(filename);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &data).unwrap();
+
+        manifest.add_page(ManifestPage {
+            index,
+            episode,
+            url: format!("https://example.com/{}", index),
+            filename: filename.to_string(),
+            hash: hash_bytes(&data),
+            extra_files: vec![],
+            encrypted_with: None,
+            dimensions: None,
+        });
+    }
+    manifest.complete = true;
+
+    manifest
+}
+
+/// Create a small PNG image
+pub fn sample_png(seed: u8) -> Vec<u8> {
+    let image = image::RgbImage::from_fn(8, 16, |x, y| {
+        image::Rgb([seed, (x * 16) as u8, (y * 8) as u8])
+    });
+    let mut buffer = std::io::Cursor::new(vec![]);
+    image
+        .write_to(&mut buffer, image::ImageFormat::Png)
+        .unwrap();
+    buffer.into_inner()
+}
+
+/// Encrypt the page the same way the CDN does (AES-256-GCM with 16 bytes nonce)
+pub fn encrypt_page(data: &[u8], key: &[u8]) -> Vec<u8> {
+    let cipher = Aes256Gcm16::new(aes_gcm::Key::<Aes256Gcm16>::from_slice(key));
+    let nonce = [3u8; 16];
+    let ciphertext = cipher.encrypt((&nonce).into(), data).unwrap();
+
+    let mut blob = vec![2u8, 0u8];
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    blob
+}
+
+/// Encrypt the AES key with the client public key, this is the volume `hash`
+pub fn encrypt_aes_key(public_key: &RsaPublicKey, key: &[u8]) -> String {
+    let mut rng = rand::thread_rng();
+    let encrypted = public_key
+        .encrypt(&mut rng, rsa::Oaep::new::<Sha256>(), key)
+        .unwrap();
+
+    general_purpose::STANDARD.encode(encrypted)
+}
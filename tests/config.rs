@@ -0,0 +1,175 @@
+mod common;
+
+use std::sync::Mutex;
+
+use emancipated::config::{
+    change_passphrase, find_any_config, get_config, get_config_path, get_default_account,
+    get_user_path, google_auth, quarantine_file, remove_config, save_config, scan_configs,
+    set_default_account, temporary_path, unlock, Config, ConfigError, Passphrase,
+};
+
+/// The tests that scan every account share the same user path
+static ACCOUNTS: Mutex<()> = Mutex::new(());
+
+fn make_config(email: &str) -> Config {
+    let response = google_auth::VerifyPasswordResponseMinimal {
+        id_token: "access-token".to_string(),
+        expires_in: "3600".to_string(),
+        refresh_token: "super-secret-refresh-token".to_string(),
+        email: email.to_string(),
+    };
+
+    Config::from(&response)
+}
+
+#[test]
+fn test_encrypt_accounts_at_rest() {
+    let _guard = ACCOUNTS.lock().unwrap();
+    common::init_user_path();
+
+    let email = "encrypted@example.com";
+    let mut config = make_config(email);
+    let (private_key, _) = config.generate_key_pair(None).unwrap();
+    save_config(&config, None).unwrap();
+
+    let config_path = get_config_path(email).unwrap();
+    assert!(!temporary_path(&config_path).exists());
+    let plain = std::fs::read_to_string(&config_path).unwrap();
+    assert!(plain.contains("super-secret-refresh-token"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(&config_path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mode = std::fs::metadata(config.private_key())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Encrypt everything
+    let passphrase = Passphrase::new("correct horse");
+    assert_eq!(change_passphrase(None, Some(&passphrase)).unwrap(), 1);
+
+    let encrypted = std::fs::read_to_string(&config_path).unwrap();
+    assert!(!encrypted.contains("super-secret-refresh-token"));
+
+    // The derived key is reused by the next saves with the same handle, only the nonce changes
+    save_config(&config, Some(&passphrase)).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    let first: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+    assert_eq!(saved["encrypted"]["salt"], first["encrypted"]["salt"]);
+    assert_ne!(saved["encrypted"]["nonce"], first["encrypted"]["nonce"]);
+    let private_pem = std::fs::read_to_string(config.private_key()).unwrap();
+    assert!(private_pem.contains("BEGIN ENCRYPTED PRIVATE KEY"));
+
+    // Locked without the passphrase
+    assert!(matches!(
+        get_config(email, None),
+        Err(ConfigError::Locked(_))
+    ));
+    assert!(config.get_key_pair(None).is_err());
+    assert!(unlock("wrong horse").is_none());
+    assert!(find_any_config(None).unwrap().is_empty());
+    assert!(matches!(
+        get_config(email, Some(&Passphrase::new("wrong horse"))),
+        Err(ConfigError::Locked(_))
+    ));
+
+    // Nothing changes while an account is locked
+    let locked = std::fs::read_to_string(&config_path).unwrap();
+    assert!(matches!(
+        change_passphrase(None, Some(&Passphrase::new("battery staple"))),
+        Err(ConfigError::Locked(_))
+    ));
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), locked);
+
+    // Unlocked with the passphrase
+    let unlocked = unlock("correct horse").unwrap();
+    let loaded = get_config(email, Some(&unlocked)).unwrap().unwrap();
+    assert_eq!(loaded.refresh_token(), "super-secret-refresh-token");
+    let (loaded_key, _) = loaded.get_key_pair(Some(&unlocked)).unwrap();
+    assert_eq!(loaded_key, private_key);
+    assert_eq!(find_any_config(Some(&unlocked)).unwrap().len(), 1);
+
+    // And back to plaintext
+    assert_eq!(change_passphrase(Some(&unlocked), None).unwrap(), 1);
+    let plain = std::fs::read_to_string(&config_path).unwrap();
+    assert!(plain.contains("super-secret-refresh-token"));
+    let private_pem = std::fs::read_to_string(config.private_key()).unwrap();
+    assert!(private_pem.contains("BEGIN PRIVATE KEY"));
+}
+
+#[test]
+fn test_remove_locked_config() {
+    let _guard = ACCOUNTS.lock().unwrap();
+    common::init_user_path();
+
+    // Encrypted, and removed without the passphrase
+    let email = "removed@example.com";
+    let passphrase = Passphrase::new("correct horse");
+    let mut config = make_config(email);
+    config.generate_key_pair(Some(&passphrase)).unwrap();
+    save_config(&config, Some(&passphrase)).unwrap();
+    set_default_account(Some(email)).unwrap();
+
+    assert!(remove_config(email).unwrap());
+    assert!(!get_config_path(email).unwrap().exists());
+    assert!(!config.private_key().exists());
+    assert!(!config.public_key().exists());
+    assert_eq!(get_default_account(), None);
+    assert!(!remove_config(email).unwrap());
+
+    // Corrupted
+    let email = "removed-broken@example.com";
+    std::fs::write(get_config_path(email).unwrap(), "{\"email\": \"removed").unwrap();
+    assert!(remove_config(email).unwrap());
+    assert!(!get_config_path(email).unwrap().exists());
+}
+
+#[test]
+fn test_corrupted_config_is_skipped() {
+    let _guard = ACCOUNTS.lock().unwrap();
+    common::init_user_path();
+
+    let user_path = get_user_path().unwrap();
+    std::fs::create_dir_all(&user_path).unwrap();
+    let broken_path = user_path.join("config_broken.json");
+    std::fs::write(&broken_path, "{\"email\": \"broken@exam").unwrap();
+
+    let broken = scan_configs(None)
+        .unwrap()
+        .into_iter()
+        .find(|file| file.path == broken_path)
+        .unwrap();
+    assert!(matches!(broken.config, Err(ConfigError::Corrupted(..))));
+    assert!(find_any_config(None).is_ok());
+
+    let target = quarantine_file(&broken_path).unwrap();
+    assert!(!broken_path.exists());
+    assert!(target.starts_with(user_path.join("quarantine")));
+    assert!(scan_configs(None)
+        .unwrap()
+        .iter()
+        .all(|file| file.path != broken_path));
+}
+
+#[test]
+fn test_invalid_expiry_is_expired() {
+    let response = google_auth::VerifyPasswordResponseMinimal {
+        id_token: "access-token".to_string(),
+        expires_in: "soon".to_string(),
+        refresh_token: "refresh-token".to_string(),
+        email: "expiry@example.com".to_string(),
+    };
+
+    assert!(Config::from(&response).is_expired());
+    assert!(!make_config("expiry@example.com").is_expired());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use emancipated::{
+    decrypt::decrypt_page,
+    export::{cbz::export_cbz, ExportError},
+};
+
+#[test]
+fn test_decrypt_kept_pages() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+
+    // The cover is not encrypted, the page is
+    let mut manifest = common::write_volume(
+        directory,
+        vec![
+            (0, None, "p000.enc", common::sample_png(0)),
+            (
+                1,
+                Some(1),
+                "p001.enc",
+                common::encrypt_page(&common::sample_png(1), &common::AES_KEY),
+            ),
+        ],
+    );
+    for page in manifest.pages.iter_mut() {
+        page.encrypted_with = Some("volume-hash".to_string());
+    }
+    assert!(manifest.is_encrypted());
+
+    let info = common::comic_info();
+    let target = directory.join("v01.cbz");
+    assert!(matches!(
+        export_cbz(directory, &manifest, &info, &target),
+        Err(ExportError::Encrypted)
+    ));
+
+    // A wrong key keeps the page encrypted
+    let mut failed = manifest.pages[1].clone();
+    assert!(decrypt_page(directory, &mut failed, &[1u8; 32]).is_err());
+    assert!(failed.encrypted_with.is_some());
+    assert!(directory.join("p001.enc").exists());
+
+    for page in manifest.pages.iter_mut() {
+        decrypt_page(directory, page, &common::AES_KEY).unwrap();
+    }
+    assert!(!manifest.is_encrypted());
+    assert_eq!(manifest.pages[0].filename, "p000.png");
+    assert_eq!(manifest.pages[1].filename, "p001.png");
+    assert!(!directory.join("p001.enc").exists());
+    assert!(manifest.is_page_complete(directory, 1));
+    assert_eq!(
+        std::fs::read(directory.join("p001.png")).unwrap(),
+        common::sample_png(1)
+    );
+
+    export_cbz(directory, &manifest, &info, &target).unwrap();
+}
@@ -0,0 +1,76 @@
+use emancipated::{
+    device::{find_profile, process_image, process_page, DeviceOptions, SplitOrder},
+    transcode::TranscodeOptions,
+};
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+/// A white landscape spread with a red left half and a blue right half inside a 10px margin
+fn sample_spread() -> DynamicImage {
+    let image = RgbImage::from_fn(220, 120, |x, y| {
+        if !(10..210).contains(&x) || !(10..110).contains(&y) {
+            Rgb([255, 255, 255])
+        } else if x < 110 {
+            Rgb([200, 0, 0])
+        } else {
+            Rgb([0, 0, 200])
+        }
+    });
+    DynamicImage::ImageRgb8(image)
+}
+
+#[test]
+fn test_trim_and_split_spread() {
+    let options = DeviceOptions {
+        trim: true,
+        split: Some(SplitOrder::RightToLeft),
+        ..Default::default()
+    };
+    let pages = process_page(sample_spread(), &options);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].dimensions(), (100, 100));
+    assert_eq!(pages[1].dimensions(), (100, 100));
+    // Right half first
+    assert_eq!(pages[0].to_rgb8().get_pixel(50, 50), &Rgb([0, 0, 200]));
+    assert_eq!(pages[1].to_rgb8().get_pixel(50, 50), &Rgb([200, 0, 0]));
+
+    // Portrait pages are never split
+    let portrait = sample_spread().rotate90();
+    assert_eq!(process_page(portrait, &options).len(), 1);
+}
+
+#[test]
+fn test_device_profile() {
+    assert!(find_profile("unknown").is_none());
+    let profile = find_profile("Kindle-Paperwhite").unwrap();
+    let options = DeviceOptions::from_profile(profile);
+    assert_eq!(options.size, Some((1236, 1648)));
+    assert!(options.grayscale);
+
+    let mut png = std::io::Cursor::new(vec![]);
+    sample_spread()
+        .resize_exact(120, 160, image::imageops::FilterType::Nearest)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+
+    let small = DeviceOptions {
+        size: Some((90, 90)),
+        ..options
+    };
+    let pages = process_image(png.get_ref(), &small, &TranscodeOptions::default()).unwrap();
+    assert_eq!(pages.len(), 1);
+    let (data, ext) = &pages[0];
+    assert_eq!(*ext, "png");
+
+    // Resized to fit while keeping the aspect ratio, and converted to grayscale for e-ink
+    let page = image::load_from_memory(data).unwrap();
+    assert_eq!(page.dimensions(), (68, 90));
+    assert!(!page.color().has_color());
+
+    // The gamma darkens the midtones but keeps the white margin
+    let gray = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(4, 4, image::Luma([128])));
+    let adjusted = process_page(gray, &options).remove(0).to_luma8();
+    assert!(adjusted.get_pixel(0, 0)[0] < 128);
+    let white = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(4, 4, image::Luma([255])));
+    let adjusted = process_page(white, &options).remove(0).to_luma8();
+    assert_eq!(adjusted.get_pixel(0, 0)[0], 255);
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use emancipated::{
+    export::{
+        cbz::{export_cbz, export_cbz_episodes},
+        comicinfo::ComicInfo,
+        epub::export_epub,
+        pdf::export_pdf,
+    },
+    manifest::{hash_bytes, ManifestFile, VolumeManifest},
+};
+
+/// Write a volume downloaded with the episode layout
+fn write_episode_volume(directory: &std::path::Path) -> VolumeManifest {
+    common::write_volume(
+        directory,
+        vec![
+            (0, None, "p000.png", common::sample_png(0)),
+            (1, Some(1), "e001/p001.png", common::sample_png(1)),
+            (2, Some(1), "e001/p002.png", common::sample_png(2)),
+            (3, Some(2), "e002/p001.png", common::sample_png(3)),
+        ],
+    )
+}
+
+fn archive_entries(path: &std::path::Path) -> Vec<String> {
+    let file = std::fs::File::open(path).unwrap();
+    let archive = zip::ZipArchive::new(file).unwrap();
+    archive.file_names().map(|name| name.to_string()).collect()
+}
+
+fn read_entry(path: &std::path::Path, name: &str) -> String {
+    let file = std::fs::File::open(path).unwrap();
+    let mut archive = zip::ZipArchive::new(file).unwrap();
+    std::io::read_to_string(archive.by_name(name).unwrap()).unwrap()
+}
+
+fn with_metadata(mut manifest: VolumeManifest) -> VolumeManifest {
+    let (comic, volume) = common::volume_metadata();
+    manifest.comic = Some(comic);
+    manifest.volume_info = Some(volume);
+    manifest
+}
+
+#[test]
+fn test_export_cbz_episode_layout() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = write_episode_volume(directory);
+
+    let info = common::comic_info();
+
+    // The entries are ordered by the volume index, not the nested filenames
+    let target = directory.join("v01.cbz");
+    export_cbz(directory, &manifest, &info, &target).unwrap();
+    let mut entries = archive_entries(&target);
+    entries.sort();
+    assert_eq!(
+        entries,
+        [
+            "ComicInfo.xml",
+            "p000.png",
+            "p001.png",
+            "p002.png",
+            "p003.png"
+        ]
+    );
+
+    let archives = export_cbz_episodes(directory, &manifest, &info, directory, "v01").unwrap();
+    assert_eq!(archives.len(), 2);
+    let mut entries = archive_entries(&archives[1]);
+    entries.sort();
+    assert_eq!(entries, ["ComicInfo.xml", "p003.png"]);
+}
+
+#[test]
+fn test_export_cbz_split_pages() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let mut manifest = write_episode_volume(directory);
+
+    // The second page was split, the extra half is saved next to it
+    let data = common::sample_png(9);
+    std::fs::write(directory.join("e001/p002_1.png"), &data).unwrap();
+    let mut page = manifest.get_page(2).unwrap().clone();
+    page.extra_files.push(ManifestFile {
+        filename: "e001/p002_1.png".to_string(),
+        hash: hash_bytes(&data),
+    });
+    manifest.add_page(page);
+    assert!(manifest.is_complete(directory));
+    assert!(manifest.is_page_complete(directory, 2));
+    assert_eq!(manifest.images().len(), 5);
+
+    let info = common::comic_info();
+
+    let target = directory.join("v01.cbz");
+    export_cbz(directory, &manifest, &info, &target).unwrap();
+    let mut entries = archive_entries(&target);
+    entries.sort();
+    assert_eq!(
+        entries,
+        [
+            "ComicInfo.xml",
+            "p000.png",
+            "p001.png",
+            "p002.png",
+            "p002_1.png",
+            "p003.png"
+        ]
+    );
+
+    // A missing extra file makes the page incomplete
+    std::fs::remove_file(directory.join("e001/p002_1.png")).unwrap();
+    assert!(!manifest.is_page_complete(directory, 2));
+    assert!(!manifest.is_complete(directory));
+}
+
+#[test]
+fn test_export_epub_layout() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = with_metadata(write_episode_volume(directory));
+
+    let target = directory.join("v01.epub");
+    export_epub(directory, &manifest, &target).unwrap();
+
+    // The mimetype must be the first entry and stored uncompressed
+    let file = std::fs::File::open(&target).unwrap();
+    let mut archive = zip::ZipArchive::new(file).unwrap();
+    let mimetype = archive.by_index(0).unwrap();
+    assert_eq!(mimetype.name(), "mimetype");
+    assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+    drop(mimetype);
+    assert_eq!(read_entry(&target, "mimetype"), "application/epub+zip");
+
+    let opf = read_entry(&target, "OEBPS/content.opf");
+    assert!(opf.contains("<spine page-progression-direction=\"rtl\">"));
+    assert!(opf.contains("<meta name=\"cover\" content=\"img_p000\"/>"));
+    assert_eq!(opf.matches("properties=\"cover-image\"").count(), 1);
+    assert_eq!(opf.matches("<itemref ").count(), 4);
+    // The metadata has no language, so it is left out
+    assert!(!opf.contains("<dc:language>"));
+
+    // The images are named by the page index, not the nested filenames of the episode layout
+    assert!(opf.contains("href=\"images/p003.png\""));
+    assert!(!opf.contains("e002/"));
+    assert!(archive_entries(&target).contains(&"OEBPS/images/p003.png".to_string()));
+    assert!(read_entry(&target, "OEBPS/pages/p003.xhtml").contains("src=\"../images/p003.png\""));
+
+    // One entry for the cover and one for each episode
+    let nav = read_entry(&target, "OEBPS/nav.xhtml");
+    assert_eq!(nav.matches("<li>").count(), 3);
+    assert!(nav.contains("<a href=\"pages/p001.xhtml\">Chapter 1</a>"));
+    assert!(nav.contains("<a href=\"pages/p003.xhtml\">Chapter 2</a>"));
+
+    // Without a cover page there is no cover metadata
+    let mut manifest = manifest;
+    manifest.pages.retain(|p| p.episode.is_some());
+    manifest.total_pages = manifest.pages.len();
+    export_epub(directory, &manifest, &target).unwrap();
+    let opf = read_entry(&target, "OEBPS/content.opf");
+    assert!(!opf.contains("<meta name=\"cover\""));
+    assert!(!opf.contains("cover-image"));
+    assert_eq!(
+        read_entry(&target, "OEBPS/nav.xhtml")
+            .matches("<li>")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_export_pdf_outline() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = with_metadata(write_episode_volume(directory));
+
+    let target = directory.join("v01.pdf");
+    export_pdf(directory, &manifest, &target).unwrap();
+
+    let doc = lopdf::Document::load(&target).unwrap();
+    assert_eq!(doc.get_pages().len(), 4);
+
+    // One bookmark for the cover and one for each episode
+    let catalog = doc.catalog().unwrap();
+    let outlines = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+    let mut titles = vec![];
+    let mut next = doc
+        .get_dictionary(outlines)
+        .unwrap()
+        .get(b"First")
+        .and_then(|o| o.as_reference())
+        .ok();
+    while let Some(id) = next {
+        let item = doc.get_dictionary(id).unwrap();
+        titles.push(lopdf::decode_text_string(item.get(b"Title").unwrap()).unwrap());
+        next = item.get(b"Next").and_then(|o| o.as_reference()).ok();
+    }
+    assert_eq!(titles, ["Cover", "Chapter 1", "Chapter 2"]);
+}
+
+#[test]
+fn test_export_cbz_comicinfo() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = write_episode_volume(directory);
+
+    let (mut comic, volume) = common::volume_metadata();
+    comic.title = "Love & <War>".to_string();
+    let info = ComicInfo::new(&comic, &volume).with_count(Some(3));
+
+    let target = directory.join("v01.cbz");
+    export_cbz(directory, &manifest, &info, &target).unwrap();
+
+    let xml = read_entry(&target, "ComicInfo.xml");
+    for field in [
+        "<Title>Volume 1</Title>",
+        "<Series>Love &amp; &lt;War&gt;</Series>",
+        "<Number>1</Number>",
+        "<Count>3</Count>",
+        "<Volume>1</Volume>",
+        "<Year>2024</Year>",
+        "<Month>1</Month>",
+        "<Day>1</Day>",
+        "<Writer>Author A, Artist B</Writer>",
+        "<Genre>Romance</Genre>",
+        "<PageCount>4</PageCount>",
+        "<Manga>YesAndRightToLeft</Manga>",
+        "<Page Image=\"0\" Type=\"FrontCover\" />",
+        "<Page Image=\"3\" />",
+    ] {
+        assert!(xml.contains(field), "missing {} in:\n{}", field, xml);
+    }
+    assert!(!xml.contains("Love & <War>"));
+}
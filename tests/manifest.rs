@@ -0,0 +1,81 @@
+mod common;
+
+use emancipated::manifest::{ManifestStatus, VolumeManifest};
+
+/// Write a finished two pages volume downloaded with the `options` fingerprint
+fn write_volume(directory: &std::path::Path, options: &str) -> VolumeManifest {
+    let mut manifest = common::write_volume(
+        directory,
+        vec![
+            (0, None, "p000.png", common::sample_png(0)),
+            (1, Some(1), "p001.png", common::sample_png(1)),
+        ],
+    );
+    manifest.options = options.to_string();
+
+    manifest
+}
+
+#[test]
+fn test_manifest_status() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = write_volume(directory, "options-a");
+    manifest.save(directory).unwrap();
+    let manifest = VolumeManifest::load(directory).unwrap().unwrap();
+
+    let status = |slug: &str, volume: i32, total_pages: usize, options: &str| {
+        manifest.status(directory, slug, volume, total_pages, options)
+    };
+    assert_eq!(
+        status(common::TEST_SLUG, 1, 2, "options-a"),
+        ManifestStatus::Complete
+    );
+
+    // Another volume or title saved in the same directory
+    assert_eq!(
+        status(common::TEST_SLUG, 2, 2, "options-a"),
+        ManifestStatus::OtherVolume
+    );
+    assert_eq!(
+        status("other-manga", 1, 2, "options-a"),
+        ManifestStatus::OtherVolume
+    );
+
+    // Different layout, transcoding, etc.
+    assert_eq!(
+        status(common::TEST_SLUG, 1, 2, "options-b"),
+        ManifestStatus::Outdated
+    );
+
+    // The cover was added, or a page is missing
+    assert_eq!(
+        status(common::TEST_SLUG, 1, 3, "options-a"),
+        ManifestStatus::Partial
+    );
+    std::fs::remove_file(directory.join("p001.png")).unwrap();
+    assert_eq!(
+        status(common::TEST_SLUG, 1, 2, "options-a"),
+        ManifestStatus::Partial
+    );
+}
+
+#[test]
+fn test_page_complete() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+    let manifest = write_volume(directory, "");
+
+    assert!(manifest.is_page_complete(directory, 0));
+    assert!(manifest.is_page_complete(directory, 1));
+    // Not recorded
+    assert!(!manifest.is_page_complete(directory, 2));
+
+    // Changed on disk
+    std::fs::write(directory.join("p001.png"), common::sample_png(9)).unwrap();
+    assert!(!manifest.is_page_complete(directory, 1));
+
+    // Missing
+    std::fs::remove_file(directory.join("p000.png")).unwrap();
+    assert!(!manifest.is_page_complete(directory, 0));
+}
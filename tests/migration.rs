@@ -0,0 +1,169 @@
+mod common;
+
+use emancipated::config::{
+    get_config, get_config_path, get_user_path, migrate, migrate_config, scan_configs,
+    upgrade_config_file, ConfigError, Migration, Passphrase, CONFIG_VERSION,
+};
+use serde_json::json;
+
+/// The original config, without the `version` field
+fn unversioned_config(email: &str) -> serde_json::Value {
+    json!({
+        "email": email,
+        "access_token": "access-token",
+        "refresh_token": "legacy-refresh-token",
+        "expires_at": 1726000000,
+        "public_key": "/home/user/.emancipatedrs/key_kp.pub",
+        "private_key": "/home/user/.emancipatedrs/key_kp.pem"
+    })
+}
+
+/// An unversioned config encrypted with the passphrase `legacy horse`
+const UNVERSIONED_ENCRYPTED: &str = r#"{
+  "email": "legacy-encrypted@example.com",
+  "encrypted": {
+    "salt": "9YnmNOAFlLe9W9SGfvhLyw==",
+    "nonce": "J7u6J+S2Ke3TUDcG",
+    "ciphertext": "GhTVgfOBOLaZgLUEivSmHnOjs8KqkGe1o2DJ+7iEnNUUU7+JXWeysEwj9AnMuWXxyyXxLii6hJ2FK0Wo4CXQoTUHwC/49ihhZ/TD/RkTLTxOkARspMr2ZqSTussGQLnL+kPPMb9hNsQ+DSMJaugQMj9jlFVQFX7c1MXen61BJnqd2lFWnRt4TU+BYSSyn1DfDO3VAvPlcnevOXLGKeVLlLfuIuQ6940MCcgatHuAPNtZBxJAXQ0="
+  }
+}"#;
+
+#[test]
+fn test_migrate_config_versions() {
+    // Unversioned
+    let (config, migrated) = migrate_config(unversioned_config("v0@example.com")).unwrap();
+    assert!(migrated);
+    assert_eq!(config.version(), CONFIG_VERSION);
+    assert_eq!(config.email(), "v0@example.com");
+    assert_eq!(config.refresh_token(), "legacy-refresh-token");
+
+    // Already the latest
+    let mut latest = unversioned_config("latest@example.com");
+    latest["version"] = CONFIG_VERSION.into();
+    let (config, migrated) = migrate_config(latest).unwrap();
+    assert!(!migrated);
+    assert_eq!(config.version(), CONFIG_VERSION);
+
+    // From the future
+    let mut future = unversioned_config("future@example.com");
+    future["version"] = (CONFIG_VERSION + 1).into();
+    assert!(matches!(
+        migrate_config(future),
+        Err(ConfigError::UnsupportedVersion(_))
+    ));
+
+    // Missing fields
+    let mut broken = unversioned_config("broken@example.com");
+    broken.as_object_mut().unwrap().remove("refresh_token");
+    assert!(matches!(migrate_config(broken), Err(ConfigError::Serde(_))));
+}
+
+#[test]
+fn test_migrate_chain() {
+    // Version 1 to 2 renames a field, and version 2 to 3 adds one
+    let migrations: [Migration; 2] = [
+        |value| {
+            let token = value.remove("refresh_token").unwrap();
+            value.insert("token".to_string(), token);
+        },
+        |value| {
+            value.insert("region".to_string(), "global".into());
+        },
+    ];
+
+    // Unversioned and version 1 run the whole chain
+    for version in [None, Some(1)] {
+        let mut value = unversioned_config("chain@example.com");
+        if let Some(version) = version {
+            value["version"] = version.into();
+        }
+        let mut value = value.as_object().unwrap().clone();
+        migrate(&mut value, version.unwrap_or(0), &migrations);
+        assert_eq!(value["version"], 3);
+        assert_eq!(value["token"], "legacy-refresh-token");
+        assert!(!value.contains_key("refresh_token"));
+        assert_eq!(value["region"], "global");
+    }
+
+    // Only the missing steps run
+    let mut value = unversioned_config("chain@example.com")
+        .as_object()
+        .unwrap()
+        .clone();
+    migrate(&mut value, 2, &migrations);
+    assert_eq!(value["version"], 3);
+    assert_eq!(value["refresh_token"], "legacy-refresh-token");
+    assert_eq!(value["region"], "global");
+
+    let mut value = unversioned_config("chain@example.com")
+        .as_object()
+        .unwrap()
+        .clone();
+    migrate(&mut value, 3, &migrations);
+    assert_eq!(value["version"], 3);
+    assert!(!value.contains_key("region"));
+}
+
+#[test]
+fn test_migrate_config_on_load() {
+    common::init_user_path();
+    std::fs::create_dir_all(get_user_path().unwrap()).unwrap();
+
+    // Plaintext, migrated in memory only
+    let email = "legacy@example.com";
+    let config_path = get_config_path(email).unwrap();
+    let original = unversioned_config(email).to_string();
+    std::fs::write(&config_path, &original).unwrap();
+
+    let config = get_config(email, None).unwrap().unwrap();
+    assert_eq!(config.version(), CONFIG_VERSION);
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), original);
+
+    // Encrypted
+    let encrypted_email = "legacy-encrypted@example.com";
+    let encrypted_path = get_config_path(encrypted_email).unwrap();
+    std::fs::write(&encrypted_path, UNVERSIONED_ENCRYPTED).unwrap();
+
+    let passphrase = Passphrase::new("legacy horse");
+    let config = get_config(encrypted_email, Some(&passphrase))
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.version(), CONFIG_VERSION);
+    assert_eq!(config.refresh_token(), "legacy-refresh-token");
+    assert_eq!(
+        std::fs::read_to_string(&encrypted_path).unwrap(),
+        UNVERSIONED_ENCRYPTED
+    );
+
+    let outdated = scan_configs(Some(&passphrase))
+        .unwrap()
+        .into_iter()
+        .filter(|file| file.outdated)
+        .count();
+    assert_eq!(outdated, 2);
+
+    // Upgrading keeps the plaintext file in plaintext, even with the passphrase set
+    assert!(upgrade_config_file(&config_path, Some(&passphrase)).unwrap());
+    let upgraded: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(upgraded["version"], CONFIG_VERSION);
+    assert_eq!(upgraded["refresh_token"], "legacy-refresh-token");
+    assert!(!upgrade_config_file(&config_path, Some(&passphrase)).unwrap());
+
+    // And the encrypted file encrypted
+    assert!(upgrade_config_file(&encrypted_path, Some(&passphrase)).unwrap());
+    let upgraded = std::fs::read_to_string(&encrypted_path).unwrap();
+    assert_ne!(upgraded, UNVERSIONED_ENCRYPTED);
+    assert!(!upgraded.contains("legacy-refresh-token"));
+    assert_eq!(
+        get_config(encrypted_email, Some(&passphrase))
+            .unwrap()
+            .unwrap()
+            .version(),
+        CONFIG_VERSION
+    );
+    assert!(scan_configs(Some(&passphrase))
+        .unwrap()
+        .iter()
+        .all(|file| !file.outdated));
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use emancipated::{retry::RetryPolicy, ClientError};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// Get the error of a request answered with the status
+async fn status_error(status: u16) -> ClientError {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(status))
+        .mount(&server)
+        .await;
+
+    let response = reqwest::get(server.uri()).await.unwrap();
+    response.error_for_status().unwrap_err().into()
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy {
+        max_retries: 5,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(1000),
+    };
+
+    // Half of the backoff is random, so the delay is between half and the full backoff
+    for _ in 0..100 {
+        for (attempt, backoff) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (30, 1000),
+        ] {
+            let delay = policy.delay(attempt);
+            assert!(delay >= Duration::from_millis(backoff / 2), "{:?}", delay);
+            assert!(delay <= Duration::from_millis(backoff), "{:?}", delay);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_transient_errors() {
+    for status in [429, 500, 502, 503] {
+        assert!(status_error(status).await.is_transient(), "{}", status);
+    }
+    for status in [400, 401, 403, 404] {
+        assert!(!status_error(status).await.is_transient(), "{}", status);
+    }
+}
+
+#[tokio::test]
+async fn test_retry_until_success() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/page"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/page"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("page"))
+        .mount(&server)
+        .await;
+
+    let policy = RetryPolicy::new(3, Duration::from_millis(1));
+    let url = format!("{}/page", server.uri());
+    let mut retries = vec![];
+    let body = policy
+        .run(
+            || async {
+                let response = reqwest::get(&url).await?.error_for_status()?;
+                Ok::<_, ClientError>(response.text().await?)
+            },
+            ClientError::is_transient,
+            |attempt, _, _| retries.push(attempt),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body, "page");
+    assert_eq!(retries, [1, 2]);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
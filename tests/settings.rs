@@ -0,0 +1,58 @@
+mod common;
+
+use emancipated::settings::{Settings, SettingsValues};
+
+#[test]
+fn test_settings_resolve_order() {
+    common::init_user_path();
+
+    let mut settings = Settings::default();
+    settings.values_mut(None).set("jobs", "8").unwrap();
+    settings.values_mut(None).set("format", "CBZ").unwrap();
+    settings.values_mut(None).set("retries", "5").unwrap();
+    settings
+        .values_mut(Some("me@example.com"))
+        .set("format", "epub")
+        .unwrap();
+    assert!(settings.values_mut(None).set("jobs", "0").is_err());
+    assert!(settings.values_mut(None).set("unknown", "1").is_err());
+    assert!(settings
+        .values_mut(None)
+        .set("dir_template", "{slug}")
+        .is_err());
+    assert!(settings
+        .values_mut(None)
+        .set("file_template", "p{page}")
+        .is_err());
+
+    settings.save().unwrap();
+    let loaded = Settings::load().unwrap();
+    assert_eq!(loaded, settings);
+
+    // Global only
+    let values = loaded.resolve(None).unwrap();
+    assert_eq!(values.jobs, Some(8));
+    assert_eq!(values.format.as_deref(), Some("cbz"));
+
+    // Account overrides the global values
+    let values = loaded.resolve(Some("me@example.com")).unwrap();
+    assert_eq!(values.jobs, Some(8));
+    assert_eq!(values.format.as_deref(), Some("epub"));
+    assert_eq!(values.retries, Some(5));
+
+    // Environment overrides everything
+    std::env::set_var(SettingsValues::env_name("retries"), "1");
+    let values = loaded.resolve(Some("me@example.com")).unwrap();
+    assert_eq!(values.retries, Some(1));
+    std::env::set_var(SettingsValues::env_name("retries"), "many");
+    assert!(loaded.resolve(None).is_err());
+
+    // The invalid value is skipped and the other values still apply
+    std::env::set_var(SettingsValues::env_name("jobs"), "2");
+    let (values, errors) = loaded.resolve_lossy(Some("me@example.com"));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(values.retries, Some(5));
+    assert_eq!(values.jobs, Some(2));
+    std::env::remove_var(SettingsValues::env_name("jobs"));
+    std::env::remove_var(SettingsValues::env_name("retries"));
+}
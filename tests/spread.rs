@@ -0,0 +1,111 @@
+mod common;
+
+use emancipated::{
+    spread::{is_spread, merge_spreads, SpreadMode},
+    transcode::TranscodeOptions,
+};
+use image::{DynamicImage, RgbImage};
+
+/// A smooth landscape artwork, the right half is the first page in reading order
+fn sample_artwork() -> RgbImage {
+    RgbImage::from_fn(120, 80, |x, y| {
+        image::Rgb([(x * 2) as u8, (y * 3) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+fn halves(artwork: &RgbImage) -> (DynamicImage, DynamicImage) {
+    let right = image::imageops::crop_imm(artwork, 60, 0, 60, 80).to_image();
+    let left = image::imageops::crop_imm(artwork, 0, 0, 60, 80).to_image();
+    (
+        DynamicImage::ImageRgb8(right),
+        DynamicImage::ImageRgb8(left),
+    )
+}
+
+fn encode_png(image: &DynamicImage) -> Vec<u8> {
+    let mut data = std::io::Cursor::new(vec![]);
+    image.write_to(&mut data, image::ImageFormat::Png).unwrap();
+    data.into_inner()
+}
+
+#[test]
+fn test_detect_spread() {
+    let (right, left) = halves(&sample_artwork());
+    assert!(is_spread(&right, &left));
+    // Wrong reading order, the edges do not meet
+    assert!(!is_spread(&left, &right));
+
+    // Blank margins always match, so they are never a spread
+    let blank = DynamicImage::ImageRgb8(RgbImage::from_pixel(60, 80, image::Rgb([255; 3])));
+    assert!(!is_spread(&blank, &blank));
+
+    // Different heights
+    let short = right.crop_imm(0, 0, 60, 70);
+    assert!(!is_spread(&short, &left));
+}
+
+#[test]
+fn test_merge_spreads_manifest() {
+    let temp = tempfile::tempdir().unwrap();
+    let directory = temp.path();
+
+    let (right, left) = halves(&sample_artwork());
+    let other = DynamicImage::ImageRgb8(RgbImage::from_fn(60, 80, |x, y| {
+        image::Rgb([255 - (x * 4) as u8, (y * 7 % 256) as u8, 90])
+    }));
+    let mut manifest = common::write_volume(
+        directory,
+        vec![
+            (1, Some(1), "p001.png", encode_png(&other)),
+            (2, Some(1), "p002.png", encode_png(&right)),
+            (3, Some(1), "p003.png", encode_png(&left)),
+            (4, Some(1), "p004.png", encode_png(&other)),
+        ],
+    );
+
+    let options = TranscodeOptions::default();
+    let spreads = merge_spreads(
+        directory,
+        &manifest,
+        &[1, 2, 3],
+        SpreadMode::Alongside,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(spreads.len(), 1);
+    assert_eq!(spreads[0].first, 2);
+    assert_eq!(spreads[0].filename, "p003_spread.png");
+
+    let merged = image::open(directory.join(&spreads[0].filename)).unwrap();
+    assert_eq!(merged.to_rgb8(), sample_artwork());
+
+    // The recorded sizes rule out the pair without decoding it
+    manifest.pages[1].dimensions = Some((60, 80));
+    manifest.pages[2].dimensions = Some((120, 80));
+    let skipped = merge_spreads(directory, &manifest, &[2], SpreadMode::Replace, &options).unwrap();
+    assert!(skipped.is_empty());
+    manifest.pages[2].dimensions = Some((60, 80));
+    let replaced =
+        merge_spreads(directory, &manifest, &[2], SpreadMode::Replace, &options).unwrap();
+    assert!(replaced[0].replace);
+
+    // Alongside: the spread comes right after the second page
+    manifest.spreads = spreads;
+    assert!(manifest.is_complete(directory));
+    let images: Vec<&str> = manifest.images().iter().map(|i| i.filename).collect();
+    assert_eq!(
+        images,
+        [
+            "p001.png",
+            "p002.png",
+            "p003.png",
+            "p003_spread.png",
+            "p004.png"
+        ]
+    );
+
+    // Replace: the spread takes the place of both pages
+    manifest.spreads[0].replace = true;
+    let images: Vec<&str> = manifest.images().iter().map(|i| i.filename).collect();
+    assert_eq!(images, ["p001.png", "p003_spread.png", "p004.png"]);
+}
@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use emancipated::template::{
+    sanitize_filename, Template, TemplateError, TemplateValues, DEFAULT_DIRECTORY_TEMPLATE,
+    DEFAULT_FILE_TEMPLATE,
+};
+
+fn sample_values() -> TemplateValues {
+    TemplateValues {
+        title: "Oshi no Ko: The <Idol>?".to_string(),
+        slug: "oshi-no-ko".to_string(),
+        volume: 3,
+        volume_name: "Volume 3".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_render_default_templates() {
+    let values = sample_values();
+
+    let directory = Template::parse(DEFAULT_DIRECTORY_TEMPLATE).unwrap();
+    assert_eq!(
+        directory.render(&values),
+        PathBuf::from("oshi-no-ko").join("v03")
+    );
+
+    let file = Template::parse(DEFAULT_FILE_TEMPLATE).unwrap();
+    assert!(file.contains("page"));
+    assert_eq!(
+        file.render(&values.clone().with_page(12, Some(2), "png")),
+        PathBuf::from("p012.png")
+    );
+}
+
+#[test]
+fn test_render_custom_templates() {
+    let values = sample_values().with_page(7, Some(15), "jpg");
+
+    let template = Template::parse("{title}/{volume_name}/e{episode:03}_{page}.{ext}").unwrap();
+    assert_eq!(
+        template.render(&values),
+        PathBuf::from("Oshi no Ko_ The _Idol__")
+            .join("Volume 3")
+            .join("e015_7.jpg")
+    );
+
+    // Empty directories are skipped, e.g. the cover has no episode
+    let template = Template::parse("{episode}/p{page:03}.{ext}").unwrap();
+    assert_eq!(
+        template.render(&values.clone().with_page(0, None, "jpg")),
+        PathBuf::from("p000.jpg")
+    );
+
+    // Literal braces and titles that try to escape the directory
+    let template = Template::parse("{{{slug}}}/{title}").unwrap();
+    let values = TemplateValues {
+        title: "../..".to_string(),
+        ..sample_values()
+    };
+    assert_eq!(
+        template.render(&values),
+        PathBuf::from("{oshi-no-ko}").join(".._")
+    );
+    let values = TemplateValues {
+        title: "..".to_string(),
+        ..sample_values()
+    };
+    assert_eq!(
+        template.render(&values),
+        PathBuf::from("{oshi-no-ko}").join("_")
+    );
+}
+
+#[test]
+fn test_parse_invalid_templates() {
+    assert!(matches!(
+        Template::parse("{unknown}"),
+        Err(TemplateError::UnknownVariable(_))
+    ));
+    assert!(matches!(
+        Template::parse("{title:03}"),
+        Err(TemplateError::InvalidFormat(_))
+    ));
+    assert!(matches!(
+        Template::parse("{page:abc}"),
+        Err(TemplateError::InvalidFormat(_))
+    ));
+    assert!(matches!(
+        Template::parse("{page"),
+        Err(TemplateError::UnmatchedBrace)
+    ));
+    assert!(matches!(
+        Template::parse("page}"),
+        Err(TemplateError::UnmatchedBrace)
+    ));
+    assert!(matches!(Template::parse("//"), Err(TemplateError::Empty)));
+
+    // Each volume needs its own directory, and each page its own file with the extension
+    assert!(Template::parse_directory(DEFAULT_DIRECTORY_TEMPLATE).is_ok());
+    assert!(matches!(
+        Template::parse_directory("{slug}"),
+        Err(TemplateError::MissingVariable("volume"))
+    ));
+    assert!(Template::parse_file(DEFAULT_FILE_TEMPLATE).is_ok());
+    assert!(matches!(
+        Template::parse_file("{volume}.{ext}"),
+        Err(TemplateError::MissingVariable("page"))
+    ));
+    assert!(matches!(
+        Template::parse_file("p{page:03}"),
+        Err(TemplateError::MissingVariable("ext"))
+    ));
+}
+
+#[test]
+fn test_sanitize_filename() {
+    assert_eq!(
+        sanitize_filename("a/b\\c:d*e?f\"g<h>i|j"),
+        "a_b_c_d_e_f_g_h_i_j"
+    );
+    assert_eq!(sanitize_filename("tab\there"), "tab_here");
+    assert_eq!(sanitize_filename("  trailing. . "), "trailing");
+    assert_eq!(sanitize_filename("..."), "_");
+    assert_eq!(sanitize_filename("CON"), "_CON");
+    assert_eq!(sanitize_filename("lpt1.png"), "_lpt1.png");
+    assert_eq!(sanitize_filename("COM10"), "COM10");
+    assert_eq!(sanitize_filename("推しの子"), "推しの子");
+
+    let long = "あ".repeat(100);
+    let sanitized = sanitize_filename(&long);
+    assert!(sanitized.len() <= 200);
+    assert!(long.starts_with(&sanitized));
+
+    // Only the stem is shortened, the extension is kept
+    let long = format!("{} p001.png", "あ".repeat(100));
+    let sanitized = sanitize_filename(&long);
+    assert!(sanitized.len() <= 200);
+    assert!(sanitized.ends_with("あ.png"));
+    assert!(long.starts_with(sanitized.trim_end_matches(".png")));
+
+    // A long title in the file template keeps the page extension
+    let template = Template::parse("{title} {page:03}.{ext}").unwrap();
+    let values = TemplateValues {
+        title: "Long Title ".repeat(30),
+        ..sample_values()
+    };
+    let filename = template.render(&values.with_page(1, Some(1), "jpg"));
+    assert_eq!(filename.extension().unwrap(), "jpg");
+    assert!(filename.as_os_str().len() <= 200);
+}
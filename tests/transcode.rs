@@ -0,0 +1,58 @@
+use emancipated::transcode::{transcode, OutputFormat, TranscodeOptions};
+use image::{GenericImageView, ImageFormat, Rgba, RgbaImage};
+
+fn sample_image(format: ImageFormat) -> Vec<u8> {
+    let image = RgbaImage::from_fn(8, 8, |x, y| {
+        Rgba([(x * 32) as u8, (y * 32) as u8, 200, 255])
+    });
+    let image = match format {
+        ImageFormat::Jpeg => {
+            image::DynamicImage::ImageRgb8(image::DynamicImage::from(image).to_rgb8())
+        }
+        _ => image::DynamicImage::from(image),
+    };
+
+    let mut data = std::io::Cursor::new(vec![]);
+    image.write_to(&mut data, format).unwrap();
+    data.into_inner()
+}
+
+#[test]
+fn test_transcode_lossless() {
+    let png = sample_image(ImageFormat::Png);
+    let original = image::load_from_memory(&png).unwrap();
+
+    let options = TranscodeOptions {
+        format: OutputFormat::Webp,
+        ..Default::default()
+    };
+    let (webp, ext) = transcode(&png, &options).unwrap();
+    assert_eq!(ext, "webp");
+    assert_eq!(image::guess_format(&webp).unwrap(), ImageFormat::WebP);
+
+    let decoded = image::load_from_memory(&webp).unwrap();
+    assert_eq!(decoded.dimensions(), original.dimensions());
+    assert_eq!(decoded.to_rgba8(), original.to_rgba8());
+}
+
+#[test]
+fn test_transcode_original_grayscale() {
+    let jpeg = sample_image(ImageFormat::Jpeg);
+
+    let options = TranscodeOptions {
+        grayscale: true,
+        ..Default::default()
+    };
+    let (data, ext) = transcode(&jpeg, &options).unwrap();
+    assert_eq!(ext, "jpg");
+
+    let decoded = image::load_from_memory(&data).unwrap();
+    assert_eq!(decoded.dimensions(), (8, 8));
+    assert!(!decoded.color().has_color());
+
+    // Anything other than JPEG and WebP is kept as PNG
+    let gif = sample_image(ImageFormat::Gif);
+    let (data, ext) = transcode(&gif, &TranscodeOptions::default()).unwrap();
+    assert_eq!(ext, "png");
+    assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Png);
+}